@@ -0,0 +1,153 @@
+use alloc::vec::{self, Vec};
+#[cfg(feature = "quickcheck")]
+use alloc::boxed::Box;
+
+use lazy::Shared;
+use seq::Seq;
+
+#[cfg(feature = "quickcheck")]
+use quickcheck::{Arbitrary, Gen};
+
+/// The ways of building part of a sequence.
+const WAYS: usize = 6;
+
+/// How many levels of parts `build` may split a sequence into.
+pub const DEPTH: usize = 16;
+
+/// Build a sequence of the next `n` elements, in order, choosing how to build each part with `choose`, which gives a number below its argument.
+///
+/// The parts are built by collecting, by pushing persistently or in place onto either end, by appending smaller parts, and by splitting and re-joining, so the trees have the shapes those operations give, rather than the shape `collect` gives.
+///
+/// Splitting and re-joining builds a part of the same length again, so each level of parts uses one unit of `depth`, and the parts at depth 0 are collected; otherwise `choose` could recurse without bound.
+pub fn build<T, C>(elements: &mut vec::IntoIter<T>, n: usize, depth: usize, choose: &mut C) -> Seq<T>
+    where T: Shared,
+          C: FnMut(usize) -> usize
+{
+    if n <= 1 || depth == 0 {
+        return elements.take(n).collect()
+    }
+    match choose(WAYS) {
+        0 => elements.take(n).collect(),
+        1 => elements.take(n).fold(Seq::empty(), |seq, x| seq.push_back(x)),
+        2 => {
+            let part: Vec<T> = elements.take(n).collect();
+            part.into_iter().rev().fold(Seq::empty(), |seq, x| seq.push_front(x))
+        },
+        3 => {
+            let k = choose(n + 1);
+            let mut part: Vec<T> = elements.take(n).collect();
+            let back = part.split_off(k);
+            let mut seq = Seq::empty();
+            for x in part.into_iter().rev() {
+                seq.push_front_mut(x);
+            }
+            for x in back {
+                seq.push_back_mut(x);
+            }
+            seq
+        },
+        4 => {
+            let k = choose(n + 1);
+            let left = build(elements, k, depth - 1, choose);
+            let right = build(elements, n - k, depth - 1, choose);
+            left.append(&right)
+        },
+        _ => {
+            let k = choose(n + 1);
+            let (left, right) = build(elements, n, depth - 1, choose).split(k);
+            left.append(&right)
+        },
+    }
+}
+
+/// A small pseudo-random number generator, for choosing shapes from a seed.
+pub struct Choices(u64);
+
+impl Choices {
+    pub fn new(seed: u64) -> Choices {
+        // Xorshift gets stuck at zero.
+        Choices(seed | 1)
+    }
+
+    /// A number below `n`.
+    pub fn choose(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// A description of a sequence, which quickcheck can generate and shrink, and which builds the sequence with `seq`.
+///
+/// A `Seq` can not be sent between threads, as the values quickcheck generates must be, so it is built inside the test from its elements, and a seed that chooses its shape, as `strategy::seq` does for proptest.
+///
+/// ```
+/// extern crate quickcheck;
+/// # extern crate immutable_seq;
+/// use immutable_seq::ArbitrarySeq;
+///
+/// fn split_then_append(seq: ArbitrarySeq<u32>, i: usize) -> bool {
+///     let seq = seq.seq();
+///     let (left, right) = seq.split(i);
+///     left.append(&right) == seq
+/// }
+///
+/// # fn main() {
+/// quickcheck::quickcheck(split_then_append as fn(ArbitrarySeq<u32>, usize) -> bool);
+/// # }
+/// ```
+#[cfg(feature = "quickcheck")]
+#[derive(Debug,Clone)]
+pub struct ArbitrarySeq<T> {
+    elements: Vec<T>,
+    seed: u64,
+}
+
+#[cfg(feature = "quickcheck")]
+impl<T: Shared + Clone> ArbitrarySeq<T> {
+    /// A description of a sequence of `elements`, in a shape chosen by `seed`.
+    pub fn new(elements: Vec<T>, seed: u64) -> ArbitrarySeq<T> {
+        ArbitrarySeq {
+            elements,
+            seed,
+        }
+    }
+
+    /// The sequence this describes. Each call builds it again, in the same shape.
+    pub fn seq(&self) -> Seq<T> {
+        let n = self.elements.len();
+        let mut choices = Choices::new(self.seed);
+        build(&mut self.elements.clone().into_iter(), n, DEPTH, &mut |k| choices.choose(k))
+    }
+}
+
+/// Generates sequences of up to `size` elements, with varied shapes, and shrinks them to shorter sequences, keeping the order of the elements that are left.
+#[cfg(feature = "quickcheck")]
+impl<T> Arbitrary for ArbitrarySeq<T>
+    where T: Arbitrary + Shared
+{
+    fn arbitrary<G: Gen>(g: &mut G) -> ArbitrarySeq<T> {
+        ArbitrarySeq::new(Arbitrary::arbitrary(g), g.next_u64())
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=ArbitrarySeq<T>>> {
+        let n = self.elements.len();
+        let mut counts = Vec::new();
+        let mut k = n;
+        while k > 0 {
+            counts.push(k);
+            k /= 2;
+        }
+        let elements = self.elements.clone();
+        let ends = counts.into_iter().flat_map(move |k| vec![elements[..n - k].to_vec(), elements[k..].to_vec()]);
+        let elements = self.elements.clone();
+        let removed = (0..n).map(move |i| {
+            let mut elements = elements.clone();
+            elements.remove(i);
+            elements
+        });
+        let seed = self.seed;
+        Box::new(ends.chain(removed).map(move |elements| ArbitrarySeq::new(elements, seed)))
+    }
+}
@@ -0,0 +1,378 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::ops::Deref;
+use core::iter::FromIterator;
+use core::fmt;
+use core::slice;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use lazy::{Owned, Owner, Part, Sharing};
+use measure::Measure;
+
+/// The number of elements that fit in a chunk built up by pushing elements one at a time.
+pub const CHUNK_SIZE: usize = 32;
+
+/// Fixed-size storage for the elements of one or more chunks.
+///
+/// The slots in `lo..hi` are initialized, and are never modified while the buffer is shared. A chunk that ends at `hi` (or starts at `lo`) may claim the next free slot and write a new element there; this does not affect any other chunk, since none of them can see that slot.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    lo: AtomicUsize,
+    hi: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for Buffer<T> {}
+unsafe impl<T: Send + Sync> Sync for Buffer<T> {}
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize, pos: usize) -> Buffer<T> {
+        Buffer {
+            slots: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+            lo: AtomicUsize::new(pos),
+            hi: AtomicUsize::new(pos),
+        }
+    }
+
+    /// Claim the free slot at `end`, if `end` is the end of the initialized slots.
+    fn claim_back(&self, end: usize) -> bool {
+        end < self.slots.len() &&
+            self.hi.compare_exchange(end, end + 1, Ordering::AcqRel, Ordering::Relaxed).is_ok()
+    }
+
+    /// Claim the free slot before `start`, if `start` is the start of the initialized slots.
+    fn claim_front(&self, start: usize) -> bool {
+        start > 0 &&
+            self.lo.compare_exchange(start, start - 1, Ordering::AcqRel, Ordering::Relaxed).is_ok()
+    }
+
+    /// Initialize slot `i`, which must have just been claimed by the caller.
+    unsafe fn write(&self, i: usize, x: T) {
+        (*self.slots[i].get()).write(x);
+    }
+
+    /// The values in `start..end`, which must be initialized.
+    unsafe fn slice(&self, start: usize, end: usize) -> &[T] {
+        slice::from_raw_parts(self.slots.as_ptr().add(start) as *const T, end - start)
+    }
+
+    /// Drop the values outside `start..end`.
+    ///
+    /// When the buffer is not shared, these can not be seen by any chunk.
+    fn trim(&mut self, start: usize, end: usize) {
+        let lo = *self.lo.get_mut();
+        let hi = *self.hi.get_mut();
+        *self.lo.get_mut() = start;
+        *self.hi.get_mut() = end;
+        for slot in self.slots[lo..start].iter_mut() {
+            unsafe { slot.get_mut().assume_init_drop() }
+        }
+        for slot in self.slots[end..hi].iter_mut() {
+            unsafe { slot.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Part for Buffer<T> {}
+
+impl<T> Owner for Buffer<T> {
+    type Owns = T;
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        let lo = *self.lo.get_mut();
+        let hi = *self.hi.get_mut();
+        for slot in self.slots[lo..hi].iter_mut() {
+            unsafe { slot.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+/// A short run of contiguous elements, stored in the leaves of the tree.
+///
+/// A chunk is a view of part of a buffer, which may be shared with other chunks. Pushing onto a chunk that ends at the end of the buffer's elements writes into the buffer's free space, rather than copying.
+pub struct Chunk<T,K: Sharing> {
+    buf: Owned<Buffer<T>,K>,
+    start: usize,
+    end: usize,
+}
+
+impl<T,K: Sharing> Chunk<T,K> {
+    fn new(capacity: usize, pos: usize, x: T) -> Chunk<T,K> {
+        let mut buf = Buffer::new(capacity, pos);
+        buf.slots[pos].get_mut().write(x);
+        *buf.hi.get_mut() = pos + 1;
+        Chunk {
+            buf: Owned::new(buf),
+            start: pos,
+            end: pos + 1,
+        }
+    }
+
+    /// A chunk holding only `x`.
+    pub fn single(x: T) -> Chunk<T,K> {
+        Chunk::new(1, 0, x)
+    }
+
+    /// A chunk holding only `x`, with room for `CHUNK_SIZE - 1` more elements at the back.
+    pub fn single_back(x: T) -> Chunk<T,K> {
+        Chunk::new(CHUNK_SIZE, 0, x)
+    }
+
+    /// A chunk holding only `x`, with room for `CHUNK_SIZE - 1` more elements at the front.
+    pub fn single_front(x: T) -> Chunk<T,K> {
+        Chunk::new(CHUNK_SIZE, CHUNK_SIZE - 1, x)
+    }
+
+    /// The elements of the chunk.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { self.buf.slice(self.start, self.end) }
+    }
+
+    /// The elements in `from..to`, sharing storage with `self`.
+    pub fn slice(&self, from: usize, to: usize) -> Chunk<T,K> {
+        assert!(from <= to && to <= self.len());
+        Chunk {
+            buf: self.buf.clone(),
+            start: self.start + from,
+            end: self.start + to,
+        }
+    }
+
+    /// `self` with `x` added to the back, or `Err(x)` if there is no room in the buffer.
+    pub fn push_back(&self, x: T) -> Result<Chunk<T,K>, T> {
+        if !self.buf.claim_back(self.end) {
+            return Err(x)
+        }
+        unsafe { self.buf.write(self.end, x) };
+        Ok(Chunk {
+            buf: self.buf.clone(),
+            start: self.start,
+            end: self.end + 1,
+        })
+    }
+
+    /// `self` with `x` added to the front, or `Err(x)` if there is no room in the buffer.
+    pub fn push_front(&self, x: T) -> Result<Chunk<T,K>, T> {
+        if !self.buf.claim_front(self.start) {
+            return Err(x)
+        }
+        unsafe { self.buf.write(self.start - 1, x) };
+        Ok(Chunk {
+            buf: self.buf.clone(),
+            start: self.start - 1,
+            end: self.end,
+        })
+    }
+
+    /// Add `x` to the back, in place, or return `Err(x)` if there is no room in the buffer.
+    pub fn push_back_mut(&mut self, x: T) -> Result<(), T> {
+        if let Some(buf) = Owned::get_mut(&mut self.buf) {
+            buf.trim(self.start, self.end);
+        }
+        if !self.buf.claim_back(self.end) {
+            return Err(x)
+        }
+        unsafe { self.buf.write(self.end, x) };
+        self.end += 1;
+        Ok(())
+    }
+
+    /// Add `x` to the front, in place, or return `Err(x)` if there is no room in the buffer.
+    pub fn push_front_mut(&mut self, x: T) -> Result<(), T> {
+        if let Some(buf) = Owned::get_mut(&mut self.buf) {
+            buf.trim(self.start, self.end);
+        }
+        if !self.buf.claim_front(self.start) {
+            return Err(x)
+        }
+        unsafe { self.buf.write(self.start - 1, x) };
+        self.start -= 1;
+        Ok(())
+    }
+}
+
+impl<T,K: Sharing> Chunk<T,K>
+    where T: Clone
+{
+    /// Remove the back element, in place, cloning it if the buffer is shared.
+    pub fn pop_back_mut(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None
+        }
+        let x = match Owned::get_mut(&mut self.buf) {
+            Some(buf) => {
+                buf.trim(self.start, self.end);
+                *buf.hi.get_mut() = self.end - 1;
+                unsafe { buf.slots[self.end - 1].get_mut().assume_init_read() }
+            },
+            None => self.as_slice()[self.len() - 1].clone(),
+        };
+        self.end -= 1;
+        Some(x)
+    }
+
+    /// Remove the front element, in place, cloning it if the buffer is shared.
+    pub fn pop_front_mut(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None
+        }
+        let x = match Owned::get_mut(&mut self.buf) {
+            Some(buf) => {
+                buf.trim(self.start, self.end);
+                *buf.lo.get_mut() = self.start + 1;
+                unsafe { buf.slots[self.start].get_mut().assume_init_read() }
+            },
+            None => self.as_slice()[0].clone(),
+        };
+        self.start += 1;
+        Some(x)
+    }
+
+    /// A mutable reference to the element at index `i`, copying the elements into a new buffer if the buffer is shared.
+    pub fn get_mut(&mut self, i: usize) -> &mut T {
+        assert!(i < self.len());
+        if Owned::get_mut(&mut self.buf).is_none() {
+            *self = self.iter().cloned().collect();
+        }
+        let buf = Owned::get_mut(&mut self.buf).unwrap();
+        unsafe { buf.slots[self.start + i].get_mut().assume_init_mut() }
+    }
+}
+
+impl<T,K: Sharing> Deref for Chunk<T,K> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T,K: Sharing> Clone for Chunk<T,K> {
+    fn clone(&self) -> Chunk<T,K> {
+        Chunk {
+            buf: self.buf.clone(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+impl<T,K: Sharing> FromIterator<T> for Chunk<T,K> {
+    fn from_iter<I>(iter: I) -> Chunk<T,K>
+        where I: IntoIterator<Item=T>
+    {
+        let items: Vec<T> = iter.into_iter().collect();
+        let len = items.len();
+        let mut buf = Buffer::new(len, 0);
+        for (slot, x) in buf.slots.iter_mut().zip(items) {
+            slot.get_mut().write(x);
+        }
+        *buf.hi.get_mut() = len;
+        Chunk {
+            buf: Owned::new(buf),
+            start: 0,
+            end: len,
+        }
+    }
+}
+
+impl<T,K: Sharing> Measure<usize> for Chunk<T,K> {
+    fn measure(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T,K: Sharing> fmt::Debug for Chunk<T,K>
+    where T: fmt::Debug
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::*;
+    use lazy::Local;
+
+    #[test]
+    fn test_push_back_shares_buffer() {
+        let a = Chunk::<_, Local>::single_back(0);
+        let b = a.push_back(1).unwrap();
+        let c = b.push_back(2).unwrap();
+        assert_eq!(a.as_slice(), &[0]);
+        assert_eq!(b.as_slice(), &[0, 1]);
+        assert_eq!(c.as_slice(), &[0, 1, 2]);
+        assert!(Owned::ptr_eq(&a.buf, &c.buf));
+    }
+
+    #[test]
+    fn test_push_back_after_divergence() {
+        let a = Chunk::<_, Local>::single_back(0);
+        let b = a.push_back(1).unwrap();
+        assert!(a.push_back(2).is_err());
+        assert!(b.slice(0, 1).push_back(2).is_err());
+        assert_eq!(b.push_back(2).unwrap().as_slice(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn test_push_front() {
+        let a = Chunk::<_, Local>::single_front(2);
+        let b = a.push_front(1).unwrap();
+        let c = b.push_front(0).unwrap();
+        assert_eq!(c.as_slice(), &[0, 1, 2]);
+        assert!(Chunk::<_, Local>::single_back(0).push_front(1).is_err());
+    }
+
+    #[test]
+    fn test_full() {
+        let mut chunk = Chunk::<_, Local>::single_back(0);
+        for i in 1..CHUNK_SIZE {
+            chunk = chunk.push_back(i).unwrap();
+        }
+        assert_eq!(chunk.len(), CHUNK_SIZE);
+        assert!(chunk.push_back(CHUNK_SIZE).is_err());
+        assert!(chunk.push_front(CHUNK_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_pop_mut_reuses_space() {
+        let mut chunk = Chunk::<_, Local>::single_back(0);
+        for i in 1..4 {
+            chunk.push_back_mut(i).unwrap();
+        }
+        assert_eq!(chunk.pop_back_mut(), Some(3));
+        assert_eq!(chunk.pop_front_mut(), Some(0));
+        assert_eq!(chunk.as_slice(), &[1, 2]);
+        chunk.push_back_mut(4).unwrap();
+        assert_eq!(chunk.as_slice(), &[1, 2, 4]);
+    }
+
+    #[test]
+    fn test_get_mut_copies_shared() {
+        let a: Chunk<u32,Local> = (0..4).collect();
+        let mut b = a.clone();
+        *b.get_mut(1) = 10;
+        assert_eq!(a.as_slice(), &[0, 1, 2, 3]);
+        assert_eq!(b.as_slice(), &[0, 10, 2, 3]);
+    }
+
+    #[test]
+    fn test_drop() {
+        let x = Rc::new(());
+        {
+            let a = Chunk::<_, Local>::single_back(x.clone());
+            let b = a.push_back(x.clone()).unwrap();
+            let _c = b.slice(1, 2);
+            let mut d = b.clone();
+            d.pop_front_mut();
+            assert_eq!(Rc::strong_count(&x), 3);
+        }
+        assert_eq!(Rc::strong_count(&x), 1);
+    }
+}
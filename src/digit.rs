@@ -1,7 +1,12 @@
-use std::ops;
+use core::ops;
+use core::fmt;
+use alloc::string::ToString;
 
-use lazy::Lazy;
+use lazy::{Lazy, Sharing, strict};
 use measure::Measure;
+use stats::Walk;
+use dot::Dot;
+use invariants::InvariantError;
 use node;
 use node::Node;
 use node::Node::{Node2, Node3};
@@ -10,22 +15,33 @@ use self::IterDigit::{IZero, IOne, ITwo, IThree};
 
 /// A short sequence of 2-3-trees.
 #[derive(Debug)]
-pub enum Digit<T,M> {
-    One  (Lazy<Node<T,M>>),
-    Two  (Lazy<Node<T,M>>, Lazy<Node<T,M>>),
-    Three(Lazy<Node<T,M>>, Lazy<Node<T,M>>, Lazy<Node<T,M>>),
-    Four (Lazy<Node<T,M>>, Lazy<Node<T,M>>, Lazy<Node<T,M>>, Lazy<Node<T,M>>),
+pub enum Digit<T,M,K: Sharing> {
+    One  (Lazy<Node<T,M,K>,K>),
+    Two  (Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>),
+    Three(Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>),
+    Four (Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>),
 }
 
-impl<T,M> Digit<T,M> {
+impl<T,M,K: Sharing> Digit<T,M,K> {
     /// Iterate of the items in the 2-3-trees in this digit.
-    pub fn iter<'a>(&'a self) -> Iter<'a, T, M> {
+    pub fn iter<'a>(&'a self) -> Iter<'a, T, M, K> {
         Iter::new(self)
     }
+
+    /// The nodes in this digit, from left to right.
+    pub fn nodes<'a>(&'a self) -> impl Iterator<Item=&'a Lazy<Node<T,M,K>,K>> + 'a {
+        let nodes = match *self {
+            One(ref x0) => [Some(x0), None, None, None],
+            Two(ref x0, ref x1) => [Some(x0), Some(x1), None, None],
+            Three(ref x0, ref x1, ref x2) => [Some(x0), Some(x1), Some(x2), None],
+            Four(ref x0, ref x1, ref x2, ref x3) => [Some(x0), Some(x1), Some(x2), Some(x3)],
+        };
+        IntoIterator::into_iter(nodes).flatten()
+    }
 }
 
-impl<'a,T,M> From<&'a Node<T,M>> for Digit<T,M> {
-    fn from(node: &'a Node<T,M>) -> Digit<T,M> {
+impl<'a,T,M,K: Sharing> From<&'a Node<T,M,K>> for Digit<T,M,K> {
+    fn from(node: &'a Node<T,M,K>) -> Digit<T,M,K> {
         match *node {
             Node2(_, ref x0, ref x1) =>
                 Two(x0.clone(), x1.clone()),
@@ -36,8 +52,8 @@ impl<'a,T,M> From<&'a Node<T,M>> for Digit<T,M> {
     }
 }
 
-impl<T,M> Clone for Digit<T,M> {
-    fn clone(&self) -> Digit<T,M> {
+impl<T,M,K: Sharing> Clone for Digit<T,M,K> {
+    fn clone(&self) -> Digit<T,M,K> {
         match *self {
             One(ref x0) =>
                 One(x0.clone()),
@@ -51,7 +67,7 @@ impl<T,M> Clone for Digit<T,M> {
     }
 }
 
-impl<T,M> Measure<M> for Digit<T,M>
+impl<T,M,K: Sharing> Measure<M> for Digit<T,M,K>
     where T: Measure<M>,
           M: ops::Add<Output=M> + Copy {
     fn measure(&self) -> M {
@@ -70,22 +86,23 @@ impl<T,M> Measure<M> for Digit<T,M>
 
 /// A suffix of a digit, used to track the state in `Iter`
 #[derive(Debug)]
-pub enum IterDigit<'a, T: 'a, M: 'a> {
+#[allow(clippy::enum_variant_names)]
+pub enum IterDigit<'a, T: 'a, M: 'a, K: Sharing> {
     IZero,
-    IOne  (&'a Lazy<Node<T,M>>),
-    ITwo  (&'a Lazy<Node<T,M>>, &'a Lazy<Node<T,M>>),
-    IThree(&'a Lazy<Node<T,M>>, &'a Lazy<Node<T,M>>, &'a Lazy<Node<T,M>>),
+    IOne  (&'a Lazy<Node<T,M,K>,K>),
+    ITwo  (&'a Lazy<Node<T,M,K>,K>, &'a Lazy<Node<T,M,K>,K>),
+    IThree(&'a Lazy<Node<T,M,K>,K>, &'a Lazy<Node<T,M,K>,K>, &'a Lazy<Node<T,M,K>,K>),
 }
 
 /// An iterator over the values in the leaves of the 2-3-trees in this digit
 #[derive(Debug)]
-pub struct Iter<'a, T: 'a, M: 'a> {
-    digit: IterDigit<'a,T,M>,
-    inner: node::Iter<'a,T,M>,
+pub struct Iter<'a, T: 'a, M: 'a, K: Sharing> {
+    digit: IterDigit<'a,T,M,K>,
+    inner: node::Iter<'a,T,M,K>,
 }
 
-impl<'a, T, M> Iter<'a, T, M> {
-    fn new(digit: &'a Digit<T,M>) -> Iter<'a, T, M> {
+impl<'a, T, M, K: Sharing> Iter<'a, T, M, K> {
+    fn new(digit: &'a Digit<T,M,K>) -> Iter<'a, T, M, K> {
         match *digit {
             One(ref x0) => Iter {
                 digit: IZero,
@@ -108,7 +125,7 @@ impl<'a, T, M> Iter<'a, T, M> {
     /// An `Iter` that is empty (yield no values)
     ///
     /// This is helpful in  `finger_tree::Iter`.
-    pub fn empty() -> Iter<'a, T, M> {
+    pub fn empty() -> Iter<'a, T, M, K> {
         Iter {
             inner: node::Iter::empty(),
             digit: IZero,
@@ -116,14 +133,13 @@ impl<'a, T, M> Iter<'a, T, M> {
     }
 }
 
-impl<'a, T:'a, M:'a> Iterator for Iter<'a, T, M> {
+impl<'a, T:'a, M:'a, K: Sharing> Iterator for Iter<'a, T, M, K> {
     type Item = &'a T;
     fn next(&mut self) -> Option<&'a T> {
         loop {
-            match self.inner.next() {
-                Some(x) => return Some(x),
-                None => {}
-            };
+            if let Some(x) = self.inner.next() {
+                return Some(x);
+            }
             match self.digit {
                 IZero => return None,
                 IOne(x0) => {
@@ -164,10 +180,10 @@ macro_rules! digit {
 #[macro_export]
 macro_rules! opt_digit {
     () => {
-        ::std::option::Option::None
+        ::core::option::Option::None
     };
     ($($e: expr),+) => {
-        ::std::option::Option::Some(digit!($($e),*))
+        ::core::option::Option::Some(digit!($($e),*))
     }
 }
 
@@ -239,9 +255,9 @@ macro_rules! lookup {
 //     }};
 // }
 
-pub fn lookup<T,M,P>(pred: P, i: M, digit: &Digit<T,M>) -> (&T,M)
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+pub fn lookup<T,M,K: Sharing,P>(pred: P, i: M, digit: &Digit<T,M,K>) -> (&T,M)
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool
 {
     match *digit {
@@ -256,6 +272,37 @@ pub fn lookup<T,M,P>(pred: P, i: M, digit: &Digit<T,M>) -> (&T,M)
     }
 }
 
+macro_rules! lookup_mut {
+    ($pred: expr, $i: expr ; $n0: expr) => {
+        node::lookup_mut($pred, $i, $n0)
+    };
+    ($pred: expr, $i: expr ; $n0: expr $(, $n: expr)*) => {{
+        let j = $i + $n0.measure();
+        if $pred(j) {
+            node::lookup_mut($pred, $i, $n0)
+        } else {
+            lookup_mut!($pred, j ; $($n),*)
+        }
+    }};
+}
+
+pub fn lookup_mut<T,M,K: Sharing,P>(pred: P, i: M, digit: &mut Digit<T,M,K>) -> (&mut T,M)
+    where T: Measure<M> + Clone,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(M) -> bool
+{
+    match *digit {
+        One(ref mut x0) =>
+            lookup_mut!(pred, i ; x0),
+        Two(ref mut x0, ref mut x1) =>
+            lookup_mut!(pred, i ; x0, x1),
+        Three(ref mut x0, ref mut x1, ref mut x2) =>
+            lookup_mut!(pred, i ; x0, x1, x2),
+        Four(ref mut x0, ref mut x1, ref mut x2, ref mut x3) =>
+            lookup_mut!(pred, i ; x0, x1, x2, x3),
+    }
+}
+
 macro_rules! adjust {
     ($func: expr, $pred: expr, $i: expr $(, $b: expr)*; $n0: expr) => {
         digit!($($b.clone() , )* node::adjust($func, $pred, $i, $n0))
@@ -270,9 +317,9 @@ macro_rules! adjust {
     }};
 }
 
-pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, digit: &Digit<T,M>) -> Digit<T,M>
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+pub fn adjust<T,M,K: Sharing,P,F>(func: F, pred: P, i: M, digit: &Digit<T,M,K>) -> Digit<T,M,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool,
           F: FnOnce(&T) -> T
 {
@@ -288,9 +335,240 @@ pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, digit: &Digit<T,M>) -> Digit<T,M>
     }
 }
 
+/// Look up all of the sorted `keys` in the nodes of `digit`, as `node::lookup_many` does.
+pub fn lookup_many<'a,T,M,K: Sharing,Q,P,F>(pred: &P, i: M, keys: &[Q], digit: &'a Digit<T,M,K>, found: &mut F)
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&'a T, M, &[Q])
+{
+    let mut i = i;
+    let mut keys = keys;
+    let mut lookup = |node| node::lookup_child(pred, &mut i, &mut keys, node, found);
+    match *digit {
+        One(ref x0) =>
+            lookup(x0),
+        Two(ref x0, ref x1) => {
+            lookup(x0);
+            lookup(x1);
+        },
+        Three(ref x0, ref x1, ref x2) => {
+            lookup(x0);
+            lookup(x1);
+            lookup(x2);
+        },
+        Four(ref x0, ref x1, ref x2, ref x3) => {
+            lookup(x0);
+            lookup(x1);
+            lookup(x2);
+            lookup(x3);
+        },
+    }
+}
+
+/// A copy of `digit` with the leaves that some of the sorted `keys` fall in adjusted, as by `node::adjust_many`.
+pub fn adjust_many<T,M,K: Sharing,Q,P,F>(func: &mut F, pred: &P, i: M, keys: &[Q], digit: &Digit<T,M,K>) -> Digit<T,M,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&T, M, &[Q]) -> T
+{
+    if keys.is_empty() {
+        return digit.clone()
+    }
+    let mut i = i;
+    let mut keys = keys;
+    let mut adjust = |node| node::adjust_child(func, pred, &mut i, &mut keys, node);
+    match *digit {
+        One(ref x0) =>
+            One(adjust(x0)),
+        Two(ref x0, ref x1) => {
+            let y0 = adjust(x0);
+            Two(y0, adjust(x1))
+        },
+        Three(ref x0, ref x1, ref x2) => {
+            let y0 = adjust(x0);
+            let y1 = adjust(x1);
+            Three(y0, y1, adjust(x2))
+        },
+        Four(ref x0, ref x1, ref x2, ref x3) => {
+            let y0 = adjust(x0);
+            let y1 = adjust(x1);
+            let y2 = adjust(x2);
+            Four(y0, y1, y2, adjust(x3))
+        },
+    }
+}
+
+/// `digit` with its front node replaced by `node`.
+pub fn replace_front<T,M,K: Sharing>(digit: &Digit<T,M,K>, node: Lazy<Node<T,M,K>,K>) -> Digit<T,M,K> {
+    match *digit {
+        One(_) =>
+            One(node),
+        Two(_, ref x1) =>
+            Two(node, x1.clone()),
+        Three(_, ref x1, ref x2) =>
+            Three(node, x1.clone(), x2.clone()),
+        Four(_, ref x1, ref x2, ref x3) =>
+            Four(node, x1.clone(), x2.clone(), x3.clone()),
+    }
+}
+
+/// `digit` with its back node replaced by `node`.
+pub fn replace_back<T,M,K: Sharing>(digit: &Digit<T,M,K>, node: Lazy<Node<T,M,K>,K>) -> Digit<T,M,K> {
+    match *digit {
+        One(_) =>
+            One(node),
+        Two(ref x0, _) =>
+            Two(x0.clone(), node),
+        Three(ref x0, ref x1, _) =>
+            Three(x0.clone(), x1.clone(), node),
+        Four(ref x0, ref x1, ref x2, _) =>
+            Four(x0.clone(), x1.clone(), x2.clone(), node),
+    }
+}
+
+/// Apply `func` to every element, from left to right, giving a digit with the same shape and measures.
+pub fn map<T,U,M,K: Sharing,F>(func: &mut F, digit: &Digit<T,M,K>) -> Digit<U,M,K>
+    where M: Copy,
+          F: FnMut(&T) -> U
+{
+    match *digit {
+        One(ref x0) =>
+            One(strict(node::map(func, x0))),
+        Two(ref x0, ref x1) => {
+            let x0 = strict(node::map(func, x0));
+            Two(x0, strict(node::map(func, x1)))
+        },
+        Three(ref x0, ref x1, ref x2) => {
+            let x0 = strict(node::map(func, x0));
+            let x1 = strict(node::map(func, x1));
+            Three(x0, x1, strict(node::map(func, x2)))
+        },
+        Four(ref x0, ref x1, ref x2, ref x3) => {
+            let x0 = strict(node::map(func, x0));
+            let x1 = strict(node::map(func, x1));
+            let x2 = strict(node::map(func, x2));
+            Four(x0, x1, x2, strict(node::map(func, x3)))
+        },
+    }
+}
+
+/// Apply `func` to every element, giving a digit with the same shape and measures, whose nodes are mapped when they are forced.
+pub fn lazy_map<T,U,M,K: Sharing,F>(func: &Lazy<F,K>, digit: &Digit<T,M,K>) -> Digit<U,M,K>
+    where T: 'static,
+          M: Copy,
+          F: Fn(&T) -> U + 'static
+{
+    match *digit {
+        One(ref x0) =>
+            One(node::lazy_map(func, x0)),
+        Two(ref x0, ref x1) =>
+            Two(node::lazy_map(func, x0), node::lazy_map(func, x1)),
+        Three(ref x0, ref x1, ref x2) =>
+            Three(node::lazy_map(func, x0), node::lazy_map(func, x1), node::lazy_map(func, x2)),
+        Four(ref x0, ref x1, ref x2, ref x3) =>
+            Four(node::lazy_map(func, x0), node::lazy_map(func, x1), node::lazy_map(func, x2), node::lazy_map(func, x3)),
+    }
+}
+
+/// Reverse the order of the nodes, and of the leaves in each, giving a digit whose nodes are reversed when they are forced.
+pub fn reverse<T,M,K: Sharing>(leaf: fn(&T) -> T, digit: &Digit<T,M,K>) -> Digit<T,M,K>
+    where M: Copy
+{
+    match *digit {
+        One(ref x0) =>
+            One(node::reverse(leaf, x0)),
+        Two(ref x0, ref x1) =>
+            Two(node::reverse(leaf, x1), node::reverse(leaf, x0)),
+        Three(ref x0, ref x1, ref x2) =>
+            Three(node::reverse(leaf, x2), node::reverse(leaf, x1), node::reverse(leaf, x0)),
+        Four(ref x0, ref x1, ref x2, ref x3) =>
+            Four(node::reverse(leaf, x3), node::reverse(leaf, x2), node::reverse(leaf, x1), node::reverse(leaf, x0)),
+    }
+}
+
+/// Add the digit, and the evaluated nodes in it, to `walk`, without forcing anything.
+pub fn stats<T,M,K: Sharing>(digit: &Digit<T,M,K>, walk: &mut Walk) {
+    let size = match *digit {
+        One(ref x0) => {
+            node::stats(x0, walk);
+            1
+        },
+        Two(ref x0, ref x1) => {
+            node::stats(x0, walk);
+            node::stats(x1, walk);
+            2
+        },
+        Three(ref x0, ref x1, ref x2) => {
+            node::stats(x0, walk);
+            node::stats(x1, walk);
+            node::stats(x2, walk);
+            3
+        },
+        Four(ref x0, ref x1, ref x2, ref x3) => {
+            node::stats(x0, walk);
+            node::stats(x1, walk);
+            node::stats(x2, walk);
+            node::stats(x3, walk);
+            4
+        },
+    };
+    walk.stats.digits[size - 1] += 1;
+}
+
+/// Draw the digit as the node `id`, and the evaluated nodes in it, with an edge from `from`, without forcing anything.
+pub fn dot<T,M,K: Sharing>(from: &str, id: &str, digit: &Digit<T,M,K>, graph: &mut Dot)
+    where T: Measure<M>,
+          M: fmt::Display + Copy
+{
+    match *digit {
+        One(ref x0) => {
+            graph.part(from, id, "One".to_string());
+            node::dot(id, x0, graph);
+        },
+        Two(ref x0, ref x1) => {
+            graph.part(from, id, "Two".to_string());
+            node::dot(id, x0, graph);
+            node::dot(id, x1, graph);
+        },
+        Three(ref x0, ref x1, ref x2) => {
+            graph.part(from, id, "Three".to_string());
+            node::dot(id, x0, graph);
+            node::dot(id, x1, graph);
+            node::dot(id, x2, graph);
+        },
+        Four(ref x0, ref x1, ref x2, ref x3) => {
+            graph.part(from, id, "Four".to_string());
+            node::dot(id, x0, graph);
+            node::dot(id, x1, graph);
+            node::dot(id, x2, graph);
+            node::dot(id, x3, graph);
+        },
+    }
+}
+
+/// Check the nodes in the digit, which is at `level` of the spine, forcing them, and give the measure of the digit.
+pub fn check<T,M,K: Sharing>(digit: &Digit<T,M,K>, level: usize) -> Result<M, InvariantError>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy + PartialEq
+{
+    Ok(match *digit {
+        One(ref x0) =>
+            node::check(x0, level, level)?,
+        Two(ref x0, ref x1) =>
+            node::check(x0, level, level)? + node::check(x1, level, level)?,
+        Three(ref x0, ref x1, ref x2) =>
+            node::check(x0, level, level)? + node::check(x1, level, level)? + node::check(x2, level, level)?,
+        Four(ref x0, ref x1, ref x2, ref x3) =>
+            node::check(x0, level, level)? + node::check(x1, level, level)? +
+                node::check(x2, level, level)? + node::check(x3, level, level)?,
+    })
+}
+
 macro_rules! split_once {
     ($pred: expr, $i: expr $(, $b: expr)* ; $n0: expr) => {
-        (opt_digit!($( $b.clone() ),*) , $n0, ::std::option::Option::None)
+        (opt_digit!($( $b.clone() ),*) , $n0, ::core::option::Option::None)
     };
     ($pred: expr, $i: expr $(, $b: expr)* ; $n0: expr $(, $n: expr)*) => {{
         let j = $i + $n0.measure();
@@ -302,10 +580,12 @@ macro_rules! split_once {
     }};
 }
 
-pub fn split_once<'a,T,M,P>(pred: &P, i: M, digit: &'a Digit<T,M>)
-                    -> (Option<Digit<T,M>>,&'a Lazy<Node<T,M>>,Option<Digit<T,M>>)
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+/// The nodes before a split point, the node at the split point, and the nodes after it.
+pub type Split<'a,T,M,K> = (Option<Digit<T,M,K>>, &'a Lazy<Node<T,M,K>,K>, Option<Digit<T,M,K>>);
+
+pub fn split_once<'a,T,M,K: Sharing,P>(pred: &P, i: M, digit: &'a Digit<T,M,K>) -> Split<'a,T,M,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool
 {
     match *digit {
@@ -324,15 +604,16 @@ pub fn split_once<'a,T,M,P>(pred: &P, i: M, digit: &'a Digit<T,M>)
 mod test {
     use super::*;
     use node::leaf;
+    use lazy::Local;
 
     #[test]
     fn test_digit_iter() {
-        let digit: Digit<u32,usize> = digit!(
+        let digit: Digit<u32,usize,Local> = digit!(
             leaf(0),
             leaf(1),
             leaf(2),
             leaf(3));
-        let result:Vec<u32> = digit.iter().map(|x| *x).collect();
+        let result:Vec<u32> = digit.iter().copied().collect();
         let expected:Vec<u32> = vec![0,1,2,3];
         assert_eq!(result,expected);
     }
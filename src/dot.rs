@@ -0,0 +1,145 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::fmt::Write;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use lazy::{Thunk, Sharing, Peek};
+
+/// A graph of the evaluated part of one or more trees, in Graphviz's DOT language.
+///
+/// Each thunk is drawn once, however many times it is reached, so nodes shared between trees, or between several places in one tree, have more than one edge into them. Nodes reached from more than one version are filled, and thunks that are unevaluated, running, poisoned, or redirected are dashed.
+pub struct Dot {
+    versions: usize,
+    /// Nodes reached by the current version.
+    seen: BTreeSet<String>,
+    /// Ids given to thunks, in the order they were first reached.
+    ids: BTreeMap<usize, usize>,
+    nodes: Vec<String>,
+    labels: BTreeMap<String, (String, bool)>,
+    reached: BTreeMap<String, usize>,
+    edges: Vec<String>,
+    edge_set: BTreeSet<String>,
+}
+
+impl Dot {
+    pub fn new() -> Dot {
+        Dot {
+            versions: 0,
+            seen: BTreeSet::new(),
+            ids: BTreeMap::new(),
+            nodes: Vec::new(),
+            labels: BTreeMap::new(),
+            reached: BTreeMap::new(),
+            edges: Vec::new(),
+            edge_set: BTreeSet::new(),
+        }
+    }
+
+    /// Start drawing the next version, giving the id of its root.
+    pub fn version(&mut self) -> String {
+        let id = format!("v{}", self.versions);
+        let label = format!("{}", self.versions);
+        self.versions += 1;
+        self.seen.clear();
+        self.node(&id, label, false);
+        id
+    }
+
+    /// Draw `thunk`, and any thunks it redirects to, with an edge from `from`.
+    ///
+    /// Gives the id and value of the thunk, if it has been evaluated and this is the first time the current version has reached it, so that the caller can draw the value.
+    pub fn thunk<'a, T, K: Sharing>(&mut self, from: &str, mut thunk: &'a Thunk<T, K>) -> Option<(String, &'a T)> {
+        let mut from = from.to_string();
+        loop {
+            let next = self.ids.len();
+            let id = format!("n{}", self.ids.entry(thunk.addr()).or_insert(next));
+            self.edge(&from, &id);
+            if !self.visit(&id) {
+                return None
+            }
+            match thunk.peek() {
+                Peek::Evaluated(val) =>
+                    return Some((id, val)),
+                Peek::Redirect(target) => {
+                    self.node(&id, "redirect".to_string(), true);
+                    from = id;
+                    thunk = target;
+                },
+                Peek::Unevaluated => {
+                    self.node(&id, "unevaluated".to_string(), true);
+                    return None
+                },
+                Peek::Poisoned => {
+                    self.node(&id, "poisoned".to_string(), true);
+                    return None
+                },
+                Peek::Running => {
+                    self.node(&id, "running".to_string(), true);
+                    return None
+                },
+            }
+        }
+    }
+
+    /// Draw a node that is not a thunk, such as a digit, with an edge from `from`.
+    pub fn part(&mut self, from: &str, id: &str, label: String) {
+        self.edge(from, id);
+        if self.visit(id) {
+            self.node(id, label, false);
+        }
+    }
+
+    /// Give the node `id` a label, unless it already has one.
+    pub fn node(&mut self, id: &str, label: String, dashed: bool) {
+        if !self.labels.contains_key(id) {
+            self.nodes.push(id.to_string());
+            self.labels.insert(id.to_string(), (label, dashed));
+        }
+    }
+
+    /// Draw an edge, unless it has already been drawn.
+    pub fn edge(&mut self, from: &str, to: &str) {
+        let edge = format!("{} -> {};", from, to);
+        if self.edge_set.insert(edge.clone()) {
+            self.edges.push(edge);
+        }
+    }
+
+    /// Mark `id` as reached by the current version, returning false if it already was.
+    fn visit(&mut self, id: &str) -> bool {
+        if !self.seen.insert(id.to_string()) {
+            return false
+        }
+        *self.reached.entry(id.to_string()).or_insert(0) += 1;
+        true
+    }
+
+    /// The graph, as a DOT `digraph`.
+    pub fn finish(self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph seq {\n    node [shape=box];\n");
+        for id in &self.nodes {
+            let (ref label, dashed) = self.labels[id];
+            let mut styles = Vec::new();
+            if dashed {
+                styles.push("dashed");
+            }
+            if self.reached.get(id).cloned().unwrap_or(0) > 1 {
+                styles.push("filled");
+            }
+            let _ = write!(out, "    {} [label=\"{}\"", id, label);
+            if id.starts_with('v') {
+                out.push_str(", shape=plaintext");
+            }
+            if !styles.is_empty() {
+                let _ = write!(out, ", style=\"{}\"", styles.join(","));
+            }
+            out.push_str("];\n");
+        }
+        for edge in &self.edges {
+            let _ = writeln!(out, "    {}", edge);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
@@ -1,41 +1,62 @@
-use std::ops::Add;
+use core::ops::Add;
+use core::mem;
+use core::fmt;
+use alloc::collections::VecDeque;
+use alloc::string::ToString;
 
-use lazy::{Lazy,strict,value,redirect};
+use lazy::{Lazy,Sharing,strict,value,redirect};
 
 use digit;
 use digit::Digit;
 use digit::Digit::{One, Two, Three, Four};
 use self::FingerTree::{Empty, Single, Deep};
 use node::{Node, node3};
-use node::Node::{Leaf};
+use node::Node::{Leaf, Node2, Node3};
 use node;
 use measure::Measure;
 use zero::Zero;
+use stats::Walk;
+use dot::Dot;
+use invariants;
+use invariants::InvariantError;
 
 #[derive(Debug)]
-pub enum FingerTree<T,M> {
+pub enum FingerTree<T,M,K: Sharing> {
     Empty,
-    Single(Lazy<Node<T,M>>),
-    Deep(M, Digit<T,M>, Lazy<FingerTree<T,M>>, Digit<T,M>)
+    Single(Lazy<Node<T,M,K>,K>),
+    Deep(M, Digit<T,M,K>, Lazy<FingerTree<T,M,K>,K>, Digit<T,M,K>)
 }
 
-impl<T,M> FingerTree<T,M> {
-    pub fn iter(&self) -> Iter<T,M> {
+impl<T,M,K: Sharing> Clone for FingerTree<T,M,K>
+    where M: Copy
+{
+    fn clone(&self) -> FingerTree<T,M,K> {
+        match *self {
+            Empty => Empty,
+            Single(ref x) => Single(x.clone()),
+            Deep(m, ref left, ref middle, ref right) =>
+                Deep(m, left.clone(), middle.clone(), right.clone()),
+        }
+    }
+}
+
+impl<T,M,K: Sharing> FingerTree<T,M,K> {
+    pub fn iter(&self) -> Iter<'_, T, M, K> {
         Iter::new(self)
     }
 }
-pub fn empty<T,M>() -> Lazy<FingerTree<T,M>> {
+pub fn empty<T,M,K: Sharing>() -> Lazy<FingerTree<T,M,K>,K> {
     strict(Empty)
 }
-pub fn single<T,M>(node: Lazy<Node<T,M>>) -> Lazy<FingerTree<T,M>> {
+pub fn single<T,M,K: Sharing>(node: Lazy<Node<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K> {
     strict(Single(node))
 }
-pub fn deep<T,M>(left: Digit<T,M>, middle: Lazy<FingerTree<T,M>>, right: Digit<T,M>)
-                 -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn deep<T,M,K: Sharing>(left: Digit<T,M,K>, middle: Lazy<FingerTree<T,M,K>,K>, right: Digit<T,M,K>)
+                 -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    lazy_val!{
+    lazy_val!{ after middle;
         let measure = left.measure() +
             middle.measure() +
             right.measure();
@@ -43,12 +64,12 @@ pub fn deep<T,M>(left: Digit<T,M>, middle: Lazy<FingerTree<T,M>>, right: Digit<T
     }
 }
 
-pub fn cons_node<T,M>(x0: Lazy<Node<T,M>>, tree: Lazy<FingerTree<T,M>>)
-                      -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn cons_node<T,M,K: Sharing>(x0: Lazy<Node<T,M,K>,K>, tree: Lazy<FingerTree<T,M,K>,K>)
+                      -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    lazy_val!{
+    lazy_val!{ after tree;
         match *tree {
             Empty => Single(x0),
             Single(ref x1) => {
@@ -83,12 +104,12 @@ pub fn cons_node<T,M>(x0: Lazy<Node<T,M>>, tree: Lazy<FingerTree<T,M>>)
     }
 }
 
-pub fn snoc_node<T,M>(tree: Lazy<FingerTree<T,M>>, x0: Lazy<Node<T,M>>)
-                      -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn snoc_node<T,M,K: Sharing>(tree: Lazy<FingerTree<T,M,K>,K>, x0: Lazy<Node<T,M,K>,K>)
+                      -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    lazy_val!{
+    lazy_val!{ after tree;
         match *tree {
             Empty => Single(x0),
             Single(ref x1) => {
@@ -141,10 +162,10 @@ macro_rules! snoc_nodes{
     };
 }
 
-fn cons_digit<T,M>(digit: Digit<T,M>, tree: Lazy<FingerTree<T,M>>)
-                   -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+fn cons_digit<T,M,K: Sharing>(digit: Digit<T,M,K>, tree: Lazy<FingerTree<T,M,K>,K>)
+                   -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match digit {
         One(x0) =>
@@ -158,10 +179,10 @@ fn cons_digit<T,M>(digit: Digit<T,M>, tree: Lazy<FingerTree<T,M>>)
     }
 }
 
-fn snoc_digit<T,M>(tree: Lazy<FingerTree<T,M>>, digit: Digit<T,M>)
-                   -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+fn snoc_digit<T,M,K: Sharing>(tree: Lazy<FingerTree<T,M,K>,K>, digit: Digit<T,M,K>)
+                   -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match digit {
         One(x0) =>
@@ -175,12 +196,12 @@ fn snoc_digit<T,M>(tree: Lazy<FingerTree<T,M>>, digit: Digit<T,M>)
     }
 }
 
-pub fn tree_tree<T,M>(left: Lazy<FingerTree<T,M>>, right: Lazy<FingerTree<T,M>>)
-                      -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn tree_tree<T,M,K: Sharing>(left: Lazy<FingerTree<T,M,K>,K>, right: Lazy<FingerTree<T,M,K>,K>)
+                      -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    lazy!{
+    lazy!{ after left, right;
         if let Empty = *left {
             return redirect(right)
         };
@@ -209,12 +230,12 @@ pub fn tree_tree<T,M>(left: Lazy<FingerTree<T,M>>, right: Lazy<FingerTree<T,M>>)
     }
 }
 
-fn tree_digit_tree<T,M>(left: Lazy<FingerTree<T,M>>, d: Digit<T,M>, right: Lazy<FingerTree<T,M>>)
-                        -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+fn tree_digit_tree<T,M,K: Sharing>(left: Lazy<FingerTree<T,M,K>,K>, d: Digit<T,M,K>, right: Lazy<FingerTree<T,M,K>,K>)
+                        -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    lazy!{
+    lazy!{ after left, right;
         if let Empty = *left {
             return redirect(cons_digit(d, right))
         };
@@ -242,45 +263,81 @@ fn tree_digit_tree<T,M>(left: Lazy<FingerTree<T,M>>, d: Digit<T,M>, right: Lazy<
     }
 }
 
-pub fn front<T,M>(tree: &Lazy<FingerTree<T,M>>) -> Option<&T> {
-    let front_node = match **tree {
-        Empty => return None,
-        Single(ref node) => node,
+fn front_node<T,M,K: Sharing>(tree: &FingerTree<T,M,K>) -> Option<&Lazy<Node<T,M,K>,K>> {
+    match *tree {
+        Empty => None,
+        Single(ref node) => Some(node),
         Deep(_, ref left, _, _) => match *left {
-            One  (ref node)          => node,
-            Two  (ref node, _)       => node,
-            Three(ref node, _, _)    => node,
-            Four (ref node, _, _, _) => node,
+            One  (ref node)          => Some(node),
+            Two  (ref node, _)       => Some(node),
+            Three(ref node, _, _)    => Some(node),
+            Four (ref node, _, _, _) => Some(node),
         }
-    };
-    match **front_node {
-        Leaf(ref x) => Some(x),
-        _ => unsafe { debug_unreachable!() },
     }
 }
 
-pub fn back<T,M>(tree: &Lazy<FingerTree<T,M>>) -> Option<&T> {
-    let back_node = match **tree {
-        Empty => return None,
-        Single(ref node) => node,
+fn back_node<T,M,K: Sharing>(tree: &FingerTree<T,M,K>) -> Option<&Lazy<Node<T,M,K>,K>> {
+    match *tree {
+        Empty => None,
+        Single(ref node) => Some(node),
         Deep(_, _, _, ref right) => match *right {
-            One  (ref node)          => node,
-            Two  (_, ref node)       => node,
-            Three(_, _, ref node)    => node,
-            Four (_, _, _, ref node) => node,
+            One  (ref node)          => Some(node),
+            Two  (_, ref node)       => Some(node),
+            Three(_, _, ref node)    => Some(node),
+            Four (_, _, _, ref node) => Some(node),
         }
-    };
-    match **back_node {
-        Leaf(ref x) => Some(x),
+    }
+}
+
+pub fn front<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> Option<&T> {
+    front_node(tree).map(|node| match **node {
+        Leaf(ref x) => x,
+        _ => unsafe { debug_unreachable!() },
+    })
+}
+
+pub fn back<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> Option<&T> {
+    back_node(tree).map(|node| match **node {
+        Leaf(ref x) => x,
         _ => unsafe { debug_unreachable!() },
+    })
+}
+
+/// `tree` with its front node replaced by `node`. Time: *O(1)*
+///
+/// `tree` must not be empty.
+pub fn replace_front<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>, node: Lazy<Node<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    match **tree {
+        Empty => panic!("replace_front on empty tree"),
+        Single(_) => single(node),
+        Deep(_, ref left, ref middle, ref right) =>
+            deep(digit::replace_front(left, node), middle.clone(), right.clone()),
     }
 }
 
-impl<'a,T,M> From<&'a Digit<T,M>> for Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+/// `tree` with its back node replaced by `node`. Time: *O(1)*
+///
+/// `tree` must not be empty.
+pub fn replace_back<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>, node: Lazy<Node<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    fn from(digit: &'a Digit<T,M>) -> Lazy<FingerTree<T,M>> {
+    match **tree {
+        Empty => panic!("replace_back on empty tree"),
+        Single(_) => single(node),
+        Deep(_, ref left, ref middle, ref right) =>
+            deep(left.clone(), middle.clone(), digit::replace_back(right, node)),
+    }
+}
+
+impl<'a,T,M,K: Sharing> From<&'a Digit<T,M,K>> for Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    fn from(digit: &'a Digit<T,M,K>) -> Lazy<FingerTree<T,M,K>,K> {
         match *digit {
             One(ref x0) =>
                 single(x0.clone()),
@@ -294,20 +351,20 @@ impl<'a,T,M> From<&'a Digit<T,M>> for Lazy<FingerTree<T,M>>
     }
 }
 
-impl<T,M> From<Digit<T,M>> for Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+impl<T,M,K: Sharing> From<Digit<T,M,K>> for Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    fn from(digit: Digit<T,M>) -> Lazy<FingerTree<T,M>> {
+    fn from(digit: Digit<T,M,K>) -> Lazy<FingerTree<T,M,K>,K> {
         (&digit).into()
     }
 }
 
-impl<T,M> From<Option<Digit<T,M>>> for Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+impl<T,M,K: Sharing> From<Option<Digit<T,M,K>>> for Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    fn from(digit: Option<Digit<T,M>>) -> Lazy<FingerTree<T,M>> {
+    fn from(digit: Option<Digit<T,M,K>>) -> Lazy<FingerTree<T,M,K>,K> {
         match digit {
             None => empty(),
             Some(digit) => digit.into(),
@@ -315,9 +372,12 @@ impl<T,M> From<Option<Digit<T,M>>> for Lazy<FingerTree<T,M>>
     }
 }
 
-fn viewl_node<T,M>(tree: &Lazy<FingerTree<T,M>>) -> (Option<&Node<T,M>>, Lazy<FingerTree<T,M>>)
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+/// The first node of a tree, if there is one, and the rest of the tree.
+type ViewL<'a,T,M,K> = (Option<&'a Node<T,M,K>>, Lazy<FingerTree<T,M,K>,K>);
+
+fn viewl_node<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> ViewL<'_,T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match **tree {
         Empty => (None, empty()),
@@ -343,19 +403,21 @@ fn viewl_node<T,M>(tree: &Lazy<FingerTree<T,M>>) -> (Option<&Node<T,M>>, Lazy<Fi
     }
 }
 
-pub fn pop_front<T,M>(tree: &Lazy<FingerTree<T,M>>) -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn pop_front<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    match viewl_node(tree) {
-        (_,rem) => rem,
-    }
+    let (_, rem) = viewl_node(tree);
+    rem
 }
 
 
-fn viewr_node<T,M>(tree: &Lazy<FingerTree<T,M>>) -> (Lazy<FingerTree<T,M>>, Option<&Node<T,M>>)
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+/// The last node of a tree, if there is one, and the rest of the tree.
+type ViewR<'a,T,M,K> = (Lazy<FingerTree<T,M,K>,K>, Option<&'a Node<T,M,K>>);
+
+fn viewr_node<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> ViewR<'_,T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match **tree {
         Empty => (empty(), None),
@@ -381,18 +443,218 @@ fn viewr_node<T,M>(tree: &Lazy<FingerTree<T,M>>) -> (Lazy<FingerTree<T,M>>, Opti
     }
 }
 
-pub fn pop_back<T,M>(tree: &Lazy<FingerTree<T,M>>) -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+pub fn pop_back<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let (rem, _) = viewr_node(tree);
+    rem
+}
+
+fn deep_value<T,M,K: Sharing>(left: Digit<T,M,K>, middle: Lazy<FingerTree<T,M,K>,K>, right: Digit<T,M,K>)
+                   -> FingerTree<T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let measure = left.measure() + middle.measure() + right.measure();
+    Deep(measure, left, middle, right)
+}
+
+fn digit_value<T,M,K: Sharing>(digit: Digit<T,M,K>) -> FingerTree<T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    match digit {
+        One(x0) =>
+            Single(x0),
+        Two(x0, x1) =>
+            deep_value(One(x0), empty(), One(x1)),
+        Three(x0, x1, x2) =>
+            deep_value(Two(x0, x1), empty(), One(x2)),
+        Four(x0, x1, x2, x3) =>
+            deep_value(Two(x0, x1), empty(), Two(x2, x3)),
+    }
+}
+
+/// A tree of the leaves below `node`, which is `height` levels above them. Time: *O(height)*
+///
+/// The nodes below `node` are shared, rather than copied, so only the nodes on its left and right edges are taken apart, as each level of the result is forced.
+pub fn from_node<T,M,K: Sharing>(node: Lazy<Node<T,M,K>,K>, height: usize) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
-    match viewr_node(tree) {
-        (rem,_) => rem,
+    let mut tree = single(node);
+    for _ in 0..height {
+        tree = lower(tree);
     }
+    tree
+}
+
+/// A tree of the children of the nodes in `tree`, which must not be leaves.
+///
+/// The children of the first and last nodes become the digits, and the nodes between them the middle.
+fn lower<T,M,K: Sharing>(tree: Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    lazy_val!{ after tree;
+        let (first, rest) = viewl_node(&tree);
+        let (middle, last) = viewr_node(&rest);
+        match (first, last) {
+            (None, _) => Empty,
+            (Some(first), None) => digit_value(first.into()),
+            (Some(first), Some(last)) => deep_value(first.into(), middle, last.into()),
+        }
+    }
+}
+
+/// Add a node to the front of `tree`, modifying the parts of the spine that
+/// are not shared in place.
+pub fn cons_node_mut<T,M,K: Sharing>(x0: Lazy<Node<T,M,K>,K>, tree: &mut Lazy<FingerTree<T,M,K>,K>)
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let t = match tree.get_mut() {
+        Some(t) => t,
+        None => {
+            *tree = cons_node(x0, tree.clone());
+            return
+        }
+    };
+    *t = match mem::replace(t, Empty) {
+        Empty => Single(x0),
+        Single(x1) =>
+            deep_value(One(x0), empty(), One(x1)),
+        Deep(measure, left, mut middle, right) => {
+            let measure = x0.measure() + measure;
+            let left = match left {
+                Four(x1, x2, x3, x4) => {
+                    cons_node_mut(node3(x2, x3, x4), &mut middle);
+                    Two(x0, x1)
+                },
+                Three(x1, x2, x3) => Four(x0, x1, x2, x3),
+                Two(x1, x2) => Three(x0, x1, x2),
+                One(x1) => Two(x0, x1),
+            };
+            Deep(measure, left, middle, right)
+        }
+    };
 }
 
-pub fn lookup<T,M,P>(pred: P, i: M, tree: &Lazy<FingerTree<T,M>>) -> (&T,M)
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static,
+/// Add a node to the back of `tree`, modifying the parts of the spine that
+/// are not shared in place.
+pub fn snoc_node_mut<T,M,K: Sharing>(tree: &mut Lazy<FingerTree<T,M,K>,K>, x0: Lazy<Node<T,M,K>,K>)
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let t = match tree.get_mut() {
+        Some(t) => t,
+        None => {
+            *tree = snoc_node(tree.clone(), x0);
+            return
+        }
+    };
+    *t = match mem::replace(t, Empty) {
+        Empty => Single(x0),
+        Single(x1) =>
+            deep_value(One(x1), empty(), One(x0)),
+        Deep(measure, left, mut middle, right) => {
+            let measure = measure + x0.measure();
+            let right = match right {
+                Four(x4, x3, x2, x1) => {
+                    snoc_node_mut(&mut middle, node3(x4, x3, x2));
+                    Two(x1, x0)
+                },
+                Three(x3, x2, x1) => Four(x3, x2, x1, x0),
+                Two(x2, x1) => Three(x2, x1, x0),
+                One(x1) => Two(x1, x0),
+            };
+            Deep(measure, left, middle, right)
+        }
+    };
+}
+
+/// Remove the front node of `tree`, modifying the parts of the spine that
+/// are not shared in place.
+pub fn viewl_node_mut<T,M,K: Sharing>(tree: &mut Lazy<FingerTree<T,M,K>,K>) -> Option<Lazy<Node<T,M,K>,K>>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let t = match tree.get_mut() {
+        Some(t) => t,
+        None => {
+            let x0 = front_node(tree).cloned();
+            *tree = pop_front(tree);
+            return x0
+        }
+    };
+    let (x0, rem) = match mem::replace(t, Empty) {
+        Empty => (None, Empty),
+        Single(x0) => (Some(x0), Empty),
+        Deep(_, left, mut middle, right) => {
+            match left {
+                Four(x0, x1, x2, x3) =>
+                    (Some(x0), deep_value(Three(x1, x2, x3), middle, right)),
+                Three(x0, x1, x2) =>
+                    (Some(x0), deep_value(Two(x1, x2), middle, right)),
+                Two(x0, x1) =>
+                    (Some(x0), deep_value(One(x1), middle, right)),
+                One(x0) => {
+                    let rem = match viewl_node_mut(&mut middle) {
+                        None => digit_value(right),
+                        Some(y) => deep_value((&*y).into(), middle, right),
+                    };
+                    (Some(x0), rem)
+                }
+            }
+        }
+    };
+    *t = rem;
+    x0
+}
+
+/// Remove the back node of `tree`, modifying the parts of the spine that
+/// are not shared in place.
+pub fn viewr_node_mut<T,M,K: Sharing>(tree: &mut Lazy<FingerTree<T,M,K>,K>) -> Option<Lazy<Node<T,M,K>,K>>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    let t = match tree.get_mut() {
+        Some(t) => t,
+        None => {
+            let x0 = back_node(tree).cloned();
+            *tree = pop_back(tree);
+            return x0
+        }
+    };
+    let (rem, x0) = match mem::replace(t, Empty) {
+        Empty => (Empty, None),
+        Single(x0) => (Empty, Some(x0)),
+        Deep(_, left, mut middle, right) => {
+            match right {
+                Four(x3, x2, x1, x0) =>
+                    (deep_value(left, middle, Three(x3, x2, x1)), Some(x0)),
+                Three(x2, x1, x0) =>
+                    (deep_value(left, middle, Two(x2, x1)), Some(x0)),
+                Two(x1, x0) =>
+                    (deep_value(left, middle, One(x1)), Some(x0)),
+                One(x0) => {
+                    let rem = match viewr_node_mut(&mut middle) {
+                        None => digit_value(left),
+                        Some(y) => deep_value(left, middle, (&*y).into()),
+                    };
+                    (rem, Some(x0))
+                }
+            }
+        }
+    };
+    *t = rem;
+    x0
+}
+
+pub fn lookup<T,M,K: Sharing,P>(pred: P, i: M, tree: &Lazy<FingerTree<T,M,K>,K>) -> (&T,M)
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy,
           P: Fn(M) -> bool
 {
     match **tree {
@@ -413,9 +675,32 @@ pub fn lookup<T,M,P>(pred: P, i: M, tree: &Lazy<FingerTree<T,M>>) -> (&T,M)
     }
 }
 
-pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, tree: &Lazy<FingerTree<T,M>>) -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static,
+pub fn lookup_mut<T,M,K: Sharing,P>(pred: P, i: M, tree: &mut Lazy<FingerTree<T,M,K>,K>) -> (&mut T,M)
+    where T: Measure<M> + Clone,
+          M: Add<Output=M> + Zero + Copy,
+          P: Fn(M) -> bool
+{
+    match *tree.make_mut() {
+        Empty => panic!("lookup in empty tree"),
+        Single(ref mut node) => node::lookup_mut(pred, i, node),
+        Deep(_, ref mut left, ref mut middle, ref mut right) => {
+            let i1 = i + left.measure();
+            if pred(i1) {
+                return digit::lookup_mut(pred, i, left)
+            }
+            let i2 = i1 + middle.measure();
+            if pred(i2) {
+                lookup_mut(pred, i1, middle)
+            } else {
+                digit::lookup_mut(pred, i2, right)
+            }
+        }
+    }
+}
+
+pub fn adjust<T,M,K: Sharing,P,F>(func: F, pred: P, i: M, tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy,
           P: Fn(M) -> bool,
           F: FnOnce(&T) -> T
 {
@@ -438,10 +723,216 @@ pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, tree: &Lazy<FingerTree<T,M>>) ->
     }
 }
 
-fn deep_left<T,M>(left: Option<Digit<T,M>>, middle: Lazy<FingerTree<T,M>>, right: Digit<T,M>)
-              -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+/// Look up all of the sorted `keys` in `tree`, calling `found` once for each leaf that some of them fall in, as `node::lookup_many` does.
+///
+/// The levels of the spine and the nodes on the paths to the keys are each visited once, so looking up *k* keys together costs less than *k* separate lookups.
+pub fn lookup_many<'a,T,M,K: Sharing,Q,P,F>(pred: &P, i: M, keys: &[Q], tree: &'a Lazy<FingerTree<T,M,K>,K>, found: &mut F)
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&'a T, M, &[Q])
+{
+    if keys.is_empty() {
+        return
+    }
+    match **tree {
+        Empty => {},
+        Single(ref node) => {
+            let mut i = i;
+            let mut keys = keys;
+            node::lookup_child(pred, &mut i, &mut keys, node, found);
+        },
+        Deep(_, ref left, ref middle, ref right) => {
+            let i1 = i + left.measure();
+            let (in_left, keys) = node::split_keys(pred, i1, keys);
+            digit::lookup_many(pred, i, in_left, left, found);
+            if keys.is_empty() {
+                return
+            }
+            let i2 = i1 + middle.measure();
+            let (in_middle, in_right) = node::split_keys(pred, i2, keys);
+            lookup_many(pred, i1, in_middle, middle, found);
+            digit::lookup_many(pred, i2, in_right, right, found);
+        }
+    }
+}
+
+/// A copy of `tree` with each leaf that some of the sorted `keys` fall in replaced by `func` of it, the measure before it, and those keys, as `node::adjust_many` does.
+pub fn adjust_many<T,M,K: Sharing,Q,P,F>(func: &mut F, pred: &P, i: M, keys: &[Q], tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&T, M, &[Q]) -> T
+{
+    if keys.is_empty() {
+        return tree.clone()
+    }
+    match **tree {
+        Empty => tree.clone(),
+        Single(ref node) => {
+            let mut i = i;
+            let mut keys = keys;
+            single(node::adjust_child(func, pred, &mut i, &mut keys, node))
+        },
+        Deep(_, ref left, ref middle, ref right) => {
+            let i1 = i + left.measure();
+            let (in_left, keys) = node::split_keys(pred, i1, keys);
+            let left = digit::adjust_many(func, pred, i, in_left, left);
+            if keys.is_empty() {
+                return deep(left, middle.clone(), right.clone())
+            }
+            let i2 = i1 + middle.measure();
+            let (in_middle, in_right) = node::split_keys(pred, i2, keys);
+            let middle = adjust_many(func, pred, i1, in_middle, middle);
+            deep(left, middle, digit::adjust_many(func, pred, i2, in_right, right))
+        }
+    }
+}
+
+/// Apply `func` to every element, from left to right, giving a tree with the same shape and measures.
+///
+/// No rebalancing is done, so this is cheaper than rebuilding the tree element by element.
+pub fn map<T,U,M,K: Sharing,F>(func: &mut F, tree: &FingerTree<T,M,K>) -> FingerTree<U,M,K>
+    where M: Copy,
+          F: FnMut(&T) -> U
+{
+    match *tree {
+        Empty => Empty,
+        Single(ref node) =>
+            Single(strict(node::map(func, node))),
+        Deep(m, ref left, ref middle, ref right) => {
+            let left = digit::map(func, left);
+            let middle = strict(map(func, middle));
+            let right = digit::map(func, right);
+            Deep(m, left, middle, right)
+        }
+    }
+}
+
+/// Apply `func` to every element, giving a tree with the same shape and measures.
+///
+/// Each node of the new tree is only mapped when it is forced, so looking up a single element of the result only maps the nodes on the path to it.
+pub fn lazy_map<T,U,M,K: Sharing,F>(func: &Lazy<F,K>, tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<U,M,K>,K>
+    where T: 'static,
+          M: Copy,
+          F: Fn(&T) -> U + 'static
+{
+    let func = func.clone();
+    let tree = tree.clone();
+    lazy_val!{ after tree;
+        match *tree {
+            Empty => Empty,
+            Single(ref node) =>
+                Single(node::lazy_map(&func, node)),
+            Deep(m, ref left, ref middle, ref right) =>
+                Deep(m, digit::lazy_map(&func, left), lazy_map(&func, middle), digit::lazy_map(&func, right)),
+        }
+    }
+}
+
+/// Reverse the order of the elements, applying `leaf` to each, giving a tree with the same measure, which must not depend on the order of the elements.
+///
+/// As with `lazy_map`, each level and node of the result is only reversed when it is forced.
+pub fn reverse<T,M,K: Sharing>(leaf: fn(&T) -> T, tree: &Lazy<FingerTree<T,M,K>,K>) -> Lazy<FingerTree<T,M,K>,K>
+    where M: Copy
+{
+    let tree = tree.clone();
+    lazy_val!{ after tree;
+        match *tree {
+            Empty => Empty,
+            Single(ref node) =>
+                Single(node::reverse(leaf, node)),
+            Deep(m, ref left, ref middle, ref right) =>
+                Deep(m, digit::reverse(leaf, right), reverse(leaf, middle), digit::reverse(leaf, left)),
+        }
+    }
+}
+
+/// Add the evaluated levels of the tree, and their nodes, to `walk`, without forcing anything.
+pub fn stats<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>, walk: &mut Walk) {
+    let mut level = tree;
+    let mut depth = 0;
+    while let Some(tree) = walk.thunk(level) {
+        match *tree {
+            Empty => break,
+            Single(ref node) => {
+                depth += 1;
+                node::stats(node, walk);
+                break
+            },
+            Deep(_, ref left, ref middle, ref right) => {
+                depth += 1;
+                digit::stats(left, walk);
+                digit::stats(right, walk);
+                level = middle;
+            },
+        }
+    }
+    walk.stats.depth = depth;
+}
+
+/// Draw the evaluated levels of the tree, and their nodes, with an edge from `from`, without forcing anything.
+pub fn dot<T,M,K: Sharing>(from: &str, tree: &Lazy<FingerTree<T,M,K>,K>, graph: &mut Dot)
+    where T: Measure<M>,
+          M: fmt::Display + Copy
+{
+    let mut from = from.to_string();
+    let mut level = tree;
+    while let Some((id, tree)) = graph.thunk(&from, level) {
+        match *tree {
+            Empty => {
+                graph.node(&id, "Empty".to_string(), false);
+                break
+            },
+            Single(ref node) => {
+                graph.node(&id, "Single".to_string(), false);
+                node::dot(&id, node, graph);
+                break
+            },
+            Deep(m, ref left, ref middle, ref right) => {
+                graph.node(&id, format!("Deep\\n{}", m), false);
+                digit::dot(&id, &format!("{}l", id), left, graph);
+                digit::dot(&id, &format!("{}r", id), right, graph);
+                from = id;
+                level = middle;
+            },
+        }
+    }
+}
+
+/// Check that the measures cached in the tree match its contents, and that the nodes in each level of the spine are one higher than those in the level above. This forces the whole tree.
+///
+/// Digits always hold one to four nodes, so they do not need to be checked.
+pub fn check_invariants<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>) -> Result<(), InvariantError>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy + PartialEq
+{
+    check(tree, 0).map(|_| ())
+}
+
+/// Check the tree, whose root is at `level` of the spine, and give its measure.
+fn check<T,M,K: Sharing>(tree: &Lazy<FingerTree<T,M,K>,K>, level: usize) -> Result<M, InvariantError>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy + PartialEq
+{
+    match *invariants::force(tree)? {
+        Empty => Ok(M::zero()),
+        Single(ref node) => node::check(node, level, level),
+        Deep(m, ref left, ref middle, ref right) => {
+            let measure = digit::check(left, level)? + check(middle, level + 1)? + digit::check(right, level)?;
+            if measure == m {
+                Ok(measure)
+            } else {
+                Err(InvariantError::Measure { level })
+            }
+        },
+    }
+}
+
+fn deep_left<T,M,K: Sharing>(left: Option<Digit<T,M,K>>, middle: Lazy<FingerTree<T,M,K>,K>, right: Digit<T,M,K>)
+              -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match left {
         Some(left) => deep(left, middle, right),
@@ -455,10 +946,10 @@ fn deep_left<T,M>(left: Option<Digit<T,M>>, middle: Lazy<FingerTree<T,M>>, right
     }
 }
 
-fn deep_right<T,M>(left: Digit<T,M>, middle: Lazy<FingerTree<T,M>>, right: Option<Digit<T,M>>)
-              -> Lazy<FingerTree<T,M>>
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static
+fn deep_right<T,M,K: Sharing>(left: Digit<T,M,K>, middle: Lazy<FingerTree<T,M,K>,K>, right: Option<Digit<T,M,K>>)
+              -> Lazy<FingerTree<T,M,K>,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
 {
     match right {
         Some(right) => deep(left, middle, right),
@@ -472,10 +963,12 @@ fn deep_right<T,M>(left: Digit<T,M>, middle: Lazy<FingerTree<T,M>>, right: Optio
     }
 }
 
-pub fn split<'a,T,M,P>(pred: &P, i: M, tree: &'a FingerTree<T,M>)
-                     -> (Lazy<FingerTree<T,M>>,&'a Lazy<Node<T,M>>,Lazy<FingerTree<T,M>>)
-    where T: Measure<M> + 'static,
-          M: Add<Output=M> + Zero + Copy + 'static,
+/// The tree before a split point, the node at the split point, and the tree after it.
+pub type Split<'a,T,M,K> = (Lazy<FingerTree<T,M,K>,K>, &'a Lazy<Node<T,M,K>,K>, Lazy<FingerTree<T,M,K>,K>);
+
+pub fn split<'a,T,M,K: Sharing,P>(pred: &P, i: M, tree: &'a FingerTree<T,M,K>) -> Split<'a,T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy,
           P: Fn(M) -> bool
 {
     match *tree {
@@ -491,7 +984,7 @@ pub fn split<'a,T,M,P>(pred: &P, i: M, tree: &'a FingerTree<T,M>)
             let i1 = i + left.measure();
             if pred(i1) {
                 let (before,x,after) = digit::split_once(pred, i, left);
-                let before:Lazy<FingerTree<T,M>> = before.into();
+                let before:Lazy<FingerTree<T,M,K>,K> = before.into();
                 let after = deep_left(after, middle.clone(), right.clone());
                 return (before, x, after)
             }
@@ -506,7 +999,7 @@ pub fn split<'a,T,M,P>(pred: &P, i: M, tree: &'a FingerTree<T,M>)
             } else {
                 let (before,x,after) = digit::split_once(pred, i2, right);
                 let before = deep_right(left.clone(), middle.clone(), before);
-                let after:Lazy<FingerTree<T,M>> = after.into();
+                let after:Lazy<FingerTree<T,M,K>,K> = after.into();
                 (before, x, after)
             }
         }
@@ -514,84 +1007,208 @@ pub fn split<'a,T,M,P>(pred: &P, i: M, tree: &'a FingerTree<T,M>)
 }
 
 #[derive(Debug)]
-pub enum IterFrame<'a, T:'a, M:'a> {
-    NodeFrame(&'a Node<T,M>),
-    FingerTreeFrame(&'a FingerTree<T,M>),
+pub enum IterFrame<'a, T:'a, M:'a, K: Sharing> {
+    NodeFrame(&'a Node<T,M,K>),
+    FingerTreeFrame(&'a FingerTree<T,M,K>),
 }
 use self::IterFrame::{NodeFrame, FingerTreeFrame};
 
+impl<'a, T, M, K: Sharing> Clone for IterFrame<'a, T, M, K> {
+    fn clone(&self) -> IterFrame<'a, T, M, K> {
+        *self
+    }
+}
+
+impl<'a, T, M, K: Sharing> Copy for IterFrame<'a, T, M, K> {}
+
+impl<'a, T, M, K: Sharing> Measure<M> for IterFrame<'a, T, M, K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy {
+    fn measure(&self) -> M {
+        match *self {
+            NodeFrame(node) => node.measure(),
+            FingerTreeFrame(tree) => tree.measure(),
+        }
+    }
+}
+
+/// Iterator over the values in the leaves of a finger tree.
+///
+/// The remaining values are kept as a sequence of whole subtrees, which are
+/// expanded from either end as the iterator advances.
 #[derive(Debug)]
-pub struct Iter<'a, T:'a, M:'a> {
-    stack: Vec<IterFrame<'a,T,M>>,
-    inner: node::Iter<'a, T, M>,
+pub struct Iter<'a, T:'a, M:'a, K: Sharing> {
+    frames: VecDeque<IterFrame<'a,T,M,K>>,
 }
 
-impl<'a, T, M> Iter<'a, T, M> {
-    fn new(node: &'a FingerTree<T,M>) -> Iter<'a, T, M> {
+impl<'a, T, M, K: Sharing> Iter<'a, T, M, K> {
+    fn new(node: &'a FingerTree<T,M,K>) -> Iter<'a, T, M, K> {
+        let mut frames = VecDeque::new();
+        frames.push_back(FingerTreeFrame(node));
         Iter {
-            stack: vec![FingerTreeFrame(node)],
-            inner: node::Iter::empty(),
+            frames,
         }
     }
-    fn push_digit(&mut self, digit: &'a Digit<T,M>) {
+
+    pub fn empty() -> Iter<'a, T, M, K> {
+        Iter {
+            frames: VecDeque::new(),
+        }
+    }
+
+    fn push_front_digit(&mut self, digit: &'a Digit<T,M,K>) {
         match *digit {
             One(ref x0) =>
-                self.stack.push(NodeFrame(x0)),
+                self.frames.push_front(NodeFrame(x0)),
             Two(ref x0,ref x1) => {
-                self.stack.push(NodeFrame(x1)); self.stack.push(NodeFrame(x0));},
+                self.frames.push_front(NodeFrame(x1)); self.frames.push_front(NodeFrame(x0));},
             Three(ref x0,ref x1,ref x2) => {
-                self.stack.push(NodeFrame(x2)); self.stack.push(NodeFrame(x1));
-                self.stack.push(NodeFrame(x0));},
+                self.frames.push_front(NodeFrame(x2)); self.frames.push_front(NodeFrame(x1));
+                self.frames.push_front(NodeFrame(x0));},
             Four(ref x0,ref x1,ref x2,ref x3) => {
-                self.stack.push(NodeFrame(x3)); self.stack.push(NodeFrame(x2));
-                self.stack.push(NodeFrame(x1)); self.stack.push(NodeFrame(x0));},
+                self.frames.push_front(NodeFrame(x3)); self.frames.push_front(NodeFrame(x2));
+                self.frames.push_front(NodeFrame(x1)); self.frames.push_front(NodeFrame(x0));},
         }
     }
+
+    fn push_back_digit(&mut self, digit: &'a Digit<T,M,K>) {
+        match *digit {
+            One(ref x0) =>
+                self.frames.push_back(NodeFrame(x0)),
+            Two(ref x0,ref x1) => {
+                self.frames.push_back(NodeFrame(x0)); self.frames.push_back(NodeFrame(x1));},
+            Three(ref x0,ref x1,ref x2) => {
+                self.frames.push_back(NodeFrame(x0)); self.frames.push_back(NodeFrame(x1));
+                self.frames.push_back(NodeFrame(x2));},
+            Four(ref x0,ref x1,ref x2,ref x3) => {
+                self.frames.push_back(NodeFrame(x0)); self.frames.push_back(NodeFrame(x1));
+                self.frames.push_back(NodeFrame(x2)); self.frames.push_back(NodeFrame(x3));},
+        }
+    }
+
+    /// Replace a frame at the front with its children, or return the value if it is a leaf.
+    fn expand_front(&mut self, frame: IterFrame<'a,T,M,K>) -> Option<&'a T> {
+        match frame {
+            NodeFrame(Leaf(x)) => return Some(x),
+            NodeFrame(Node2(_, x0, x1)) => {
+                self.frames.push_front(NodeFrame(x1));
+                self.frames.push_front(NodeFrame(x0));
+            },
+            NodeFrame(Node3(_, x0, x1, x2)) => {
+                self.frames.push_front(NodeFrame(x2));
+                self.frames.push_front(NodeFrame(x1));
+                self.frames.push_front(NodeFrame(x0));
+            },
+            FingerTreeFrame(Empty) => {},
+            FingerTreeFrame(Single(x)) =>
+                self.frames.push_front(NodeFrame(x)),
+            FingerTreeFrame(Deep(_, left, middle, right)) => {
+                self.push_front_digit(right);
+                self.frames.push_front(FingerTreeFrame(middle));
+                self.push_front_digit(left);
+            },
+        }
+        None
+    }
+
+    /// Replace a frame at the back with its children, or return the value if it is a leaf.
+    fn expand_back(&mut self, frame: IterFrame<'a,T,M,K>) -> Option<&'a T> {
+        match frame {
+            NodeFrame(Leaf(x)) => return Some(x),
+            NodeFrame(Node2(_, x0, x1)) => {
+                self.frames.push_back(NodeFrame(x0));
+                self.frames.push_back(NodeFrame(x1));
+            },
+            NodeFrame(Node3(_, x0, x1, x2)) => {
+                self.frames.push_back(NodeFrame(x0));
+                self.frames.push_back(NodeFrame(x1));
+                self.frames.push_back(NodeFrame(x2));
+            },
+            FingerTreeFrame(Empty) => {},
+            FingerTreeFrame(Single(x)) =>
+                self.frames.push_back(NodeFrame(x)),
+            FingerTreeFrame(Deep(_, left, middle, right)) => {
+                self.push_back_digit(left);
+                self.frames.push_back(FingerTreeFrame(middle));
+                self.push_back_digit(right);
+            },
+        }
+        None
+    }
+
+    /// Split the remaining values into two iterators, at the point where
+    /// `pred` becomes true.
+    ///
+    /// Whole subtrees are moved to the first iterator while their accumulated
+    /// measure (starting from `i`) does not satisfy `pred`; the subtree where
+    /// `pred` becomes true is expanded until a single leaf remains, which
+    /// starts the second iterator. Time: *O(log n)*
+    pub fn split<P>(mut self, pred: &P, i: M) -> (Iter<'a,T,M,K>, Iter<'a,T,M,K>)
+        where T: Measure<M>,
+              M: Add<Output=M> + Zero + Copy,
+              P: Fn(M) -> bool
+    {
+        let mut before = VecDeque::new();
+        let mut i = i;
+        while let Some(frame) = self.frames.pop_front() {
+            let j = i + frame.measure();
+            if !pred(j) {
+                before.push_back(frame);
+                i = j;
+            } else if let NodeFrame(&Leaf(_)) = frame {
+                self.frames.push_front(frame);
+                break
+            } else {
+                self.expand_front(frame);
+            }
+        }
+        (Iter { frames: before }, self)
+    }
 }
 
-impl<'a, T:'a, M> Iterator for Iter<'a,T,M> {
+impl<'a, T:'a, M, K: Sharing> Iterator for Iter<'a,T,M,K> {
     type Item = &'a T;
     fn next(&mut self) -> Option<&'a T> {
-        if let v@Some(_) = self.inner.next() {
-            return v
-        }
-        loop {
-            match self.stack.pop() {
-                None => return None,
-                Some(NodeFrame(node)) => {
-                    self.inner = node.iter();
-                    return self.inner.next();
-                },
-                Some(FingerTreeFrame(tree)) => {
-                    match *tree {
-                        Empty => {},
-                        Single(ref x) => {
-                            self.inner = x.iter();
-                            return self.inner.next();
-                        }
-                        Deep(_, ref left, ref middle, ref right) => {
-                            self.push_digit(right);
-                            self.stack.push(FingerTreeFrame(middle));
-                            self.push_digit(left);
-                        }
-                    }
-                },
+        while let Some(frame) = self.frames.pop_front() {
+            if let v@Some(_) = self.expand_front(frame) {
+                return v
             }
         }
+        None
     }
 }
 
-impl<'a, T, M> IntoIterator for &'a FingerTree<T,M> {
+impl<'a, T, M, K: Sharing> Measure<M> for Iter<'a,T,M,K>
+    where T: Measure<M>,
+          M: Add<Output=M> + Zero + Copy
+{
+    fn measure(&self) -> M {
+        self.frames.iter().fold(M::zero(), |m, frame| m + frame.measure())
+    }
+}
+
+impl<'a, T:'a, M, K: Sharing> DoubleEndedIterator for Iter<'a,T,M,K> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        while let Some(frame) = self.frames.pop_back() {
+            if let v@Some(_) = self.expand_back(frame) {
+                return v
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T, M, K: Sharing> IntoIterator for &'a FingerTree<T,M,K> {
     type Item = &'a T;
 
-    type IntoIter = Iter<'a, T, M>;
+    type IntoIter = Iter<'a, T, M, K>;
 
-    fn into_iter(self) -> Iter<'a,T,M> {
+    fn into_iter(self) -> Iter<'a,T,M,K> {
         self.iter()
     }
 }
 
-impl<T,M> Measure<M> for FingerTree<T,M>
+impl<T,M,K: Sharing> Measure<M> for FingerTree<T,M,K>
     where T: Measure<M>,
           M: Add<Output=M> + Zero + Copy {
     fn measure(&self) -> M {
@@ -609,6 +1226,7 @@ mod test {
     use super::*;
     use node::{leaf, node2, node3};
     use digit::Digit::{One,Two};
+    use lazy::Local;
 
     #[derive(Clone)]
     struct Item<T>(T);
@@ -621,7 +1239,7 @@ mod test {
 
     #[test]
     fn test_iter_empty() {
-        let tree: Lazy<FingerTree<Item<u32>, usize>> =
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
             empty();
         let result:Vec<u32> = tree.iter().map(|&Item(x)| x).collect();
         let expected:Vec<u32> = vec![];
@@ -630,7 +1248,7 @@ mod test {
 
     #[test]
     fn test_iter_single() {
-        let tree: Lazy<FingerTree<Item<u32>, usize>> =
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
             single(leaf(Item(0)));
         let result:Vec<u32> = tree.iter().map(|&Item(x)| x).collect();
         let expected:Vec<u32> = vec![0];
@@ -639,7 +1257,7 @@ mod test {
 
     #[test]
     fn test_iter_inner_empty() {
-        let tree: Lazy<FingerTree<Item<u32>, usize>> =
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
             deep(
                 One(
                     node2(
@@ -676,9 +1294,66 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_check_invariants() {
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
+            deep(
+                Two(
+                    leaf(Item(0)),
+                    leaf(Item(1))),
+                single(
+                    node3(
+                        leaf(Item(2)),
+                        leaf(Item(3)),
+                        leaf(Item(4)))),
+                One(
+                    leaf(Item(5))));
+        assert_eq!(check_invariants(&tree), Ok(()));
+        assert_eq!(check_invariants(&empty::<Item<u32>, usize, Local>()), Ok(()));
+    }
+
+    #[test]
+    fn test_check_invariants_measure() {
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
+            strict(Deep(3, One(leaf(Item(0))), empty(), One(leaf(Item(1)))));
+        assert_eq!(check_invariants(&tree), Err(InvariantError::Measure { level: 0 }));
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
+            deep(
+                One(
+                    leaf(Item(0))),
+                single(
+                    strict(Node2(3, leaf(Item(1)), leaf(Item(2))))),
+                One(
+                    leaf(Item(3))));
+        assert_eq!(check_invariants(&tree), Err(InvariantError::Measure { level: 1 }));
+    }
+
+    #[test]
+    fn test_check_invariants_depth() {
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
+            single(
+                node2(
+                    leaf(Item(0)),
+                    leaf(Item(1))));
+        assert_eq!(check_invariants(&tree), Err(InvariantError::Depth { level: 0 }));
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
+            deep(
+                One(
+                    leaf(Item(0))),
+                single(
+                    node2(
+                        leaf(Item(1)),
+                        node2(
+                            leaf(Item(2)),
+                            leaf(Item(3))))),
+                One(
+                    leaf(Item(4))));
+        assert_eq!(check_invariants(&tree), Err(InvariantError::Depth { level: 1 }));
+    }
+
     #[test]
     fn test_iter_inner_single() {
-        let tree: Lazy<FingerTree<Item<u32>, usize>> =
+        let tree: Lazy<FingerTree<Item<u32>, usize, Local>> =
             deep(
                 One(
                     node2(
@@ -721,4 +1396,19 @@ mod test {
         let expected:Vec<u32> = (0..19).collect();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_iter_split() {
+        let mut tree: Lazy<FingerTree<Item<u32>, usize, Local>> = empty();
+        for i in 0..50 {
+            tree = snoc_node(tree, leaf(Item(i)));
+        }
+        for n in 0..51 {
+            let (before, after) = tree.iter().split(&move |i| {n < i}, 0);
+            let before:Vec<u32> = before.map(|&Item(x)| x).collect();
+            let after:Vec<u32> = after.map(|&Item(x)| x).collect();
+            assert_eq!(before, (0..n as u32).collect::<Vec<u32>>());
+            assert_eq!(after, (n as u32..50).collect::<Vec<u32>>());
+        }
+    }
 }
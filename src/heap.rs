@@ -0,0 +1,569 @@
+//! Allocation of the nodes of sequences.
+//!
+//! Every node of a sequence's tree, every level of its spine, and every chunk of its elements is held in a thunk, which is a separate allocation. By default these come from the global allocator. With the `std` feature, `NodeHeap::scope` allocates the thunks created while it runs from a `NodeAlloc` instead, such as an `Arena`, which frees all of its memory at once.
+//!
+//! The closures of unevaluated thunks, and the storage for the elements of chunks, still come from the global allocator, as do all the thunks of a `SyncSeq`, which may be freed on any thread.
+
+use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use core::cell::Cell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "sync")]
+use core::sync::atomic;
+use alloc::alloc::{alloc, dealloc, handle_alloc_error};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use lazy::Part;
+
+/// A source of memory for the thunks of sequences.
+///
+/// # Safety
+///
+/// `alloc` must return null, or a block of memory that fits `layout`, and is not used by anything else until it is passed to `dealloc`.
+pub unsafe trait NodeAlloc {
+    /// Allocate a block of memory for `layout`, which never has a size of zero, or return null if there is none.
+    fn alloc(&self, layout: Layout) -> *mut u8;
+
+    /// Free a block returned by `alloc`, given the same layout.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `alloc` on the same allocator, with the same layout, and not freed since.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout);
+}
+
+/// The global allocator, which nodes use by default.
+#[derive(Debug,Clone,Copy,Default)]
+pub struct Global;
+
+unsafe impl NodeAlloc for Global {
+    fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        dealloc(ptr, layout)
+    }
+}
+
+/// An allocator that hands out memory from large blocks, and frees the blocks all at once, when it is dropped.
+///
+/// Freeing a single node does nothing, so the memory of a family of versions is only given back once they have all been dropped. Allocating a node only bumps a pointer.
+pub struct Arena {
+    block_size: usize,
+    blocks: RefCell<Blocks>,
+}
+
+struct Blocks {
+    /// Every block, with the layout it was allocated with.
+    all: Vec<(NonNull<u8>, Layout)>,
+    /// The free space at the end of the last block.
+    next: usize,
+    end: usize,
+    /// The number of bytes handed out.
+    allocated: usize,
+}
+
+impl Arena {
+    /// The size of the blocks of a new arena.
+    pub const BLOCK_SIZE: usize = 64 * 1024;
+
+    /// An empty arena, which allocates blocks of `BLOCK_SIZE` bytes.
+    pub fn new() -> Arena {
+        Arena::with_block_size(Arena::BLOCK_SIZE)
+    }
+
+    /// An empty arena, which allocates blocks of `block_size` bytes, or larger blocks for nodes that do not fit.
+    pub fn with_block_size(block_size: usize) -> Arena {
+        let blocks = Blocks {
+            all: Vec::new(),
+            next: 0,
+            end: 0,
+            allocated: 0,
+        };
+        Arena {
+            block_size,
+            blocks: RefCell::new(blocks),
+        }
+    }
+
+    /// The number of bytes handed out to nodes.
+    pub fn allocated(&self) -> usize {
+        self.with_blocks(|blocks| blocks.allocated)
+    }
+
+    /// The number of blocks allocated from the global allocator.
+    pub fn blocks(&self) -> usize {
+        self.with_blocks(|blocks| blocks.all.len())
+    }
+
+    fn with_blocks<R, F: FnOnce(&mut Blocks) -> R>(&self, f: F) -> R {
+        f(&mut self.blocks.borrow_mut())
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Arena {
+        Arena::new()
+    }
+}
+
+unsafe impl NodeAlloc for Arena {
+    fn alloc(&self, layout: Layout) -> *mut u8 {
+        let block_size = self.block_size;
+        self.with_blocks(|blocks| {
+            let mut start = (blocks.next + layout.align() - 1) & !(layout.align() - 1);
+            if blocks.all.is_empty() || start + layout.size() > blocks.end {
+                let block = match Layout::from_size_align(block_size.max(layout.size()), layout.align().max(16)) {
+                    Ok(block) => block,
+                    Err(_) => return ptr::null_mut(),
+                };
+                let ptr = match NonNull::new(unsafe { alloc(block) }) {
+                    Some(ptr) => ptr,
+                    None => return ptr::null_mut(),
+                };
+                blocks.all.push((ptr, block));
+                start = ptr.as_ptr() as usize;
+                blocks.end = start + block.size();
+            }
+            blocks.next = start + layout.size();
+            blocks.allocated += layout.size();
+            let &(block, _) = blocks.all.last().unwrap();
+            unsafe { block.as_ptr().add(start - block.as_ptr() as usize) }
+        })
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        self.with_blocks(|blocks| {
+            for &(ptr, layout) in &blocks.all {
+                unsafe { dealloc(ptr.as_ptr(), layout) }
+            }
+            blocks.all.clear();
+        })
+    }
+}
+
+/// A handle to a `NodeAlloc`, which can allocate the nodes of sequences from it.
+///
+/// The allocator is dropped once the last handle to it, and the last node allocated from it, have been dropped. The nodes are counted without synchronization, so a heap stays on the thread that created it.
+pub struct NodeHeap<A> {
+    source: NonNull<Source>,
+    marker: PhantomData<A>,
+}
+
+impl<A: NodeAlloc + 'static> NodeHeap<A> {
+    pub fn new(alloc: A) -> NodeHeap<A> {
+        let source = Box::new(Source {
+            count: Count::new(1, false),
+            alloc: Box::new(alloc),
+        });
+        NodeHeap {
+            source: NonNull::from(Box::leak(source)),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<A: NodeAlloc> NodeHeap<A> {
+    /// The allocator.
+    pub fn allocator(&self) -> &A {
+        let alloc: &dyn NodeAlloc = &*self.source().alloc;
+        unsafe { &*(alloc as *const dyn NodeAlloc as *const A) }
+    }
+
+    /// Run `f`, allocating the thunks of the `Seq`s it creates on this thread from this heap.
+    ///
+    /// This includes the thunks created when `f` forces part of a sequence, wherever that sequence was built, and not those created later, when a sequence built by `f` is used outside of it. The thunks of a `SyncSeq` still come from the global allocator.
+    ///
+    /// ```
+    /// # extern crate immutable_seq;
+    /// # use immutable_seq::Seq;
+    /// use immutable_seq::heap::{Arena, NodeHeap};
+    ///
+    /// # fn main() {
+    /// let heap = NodeHeap::new(Arena::new());
+    /// let versions: Vec<Seq<u32>> = heap.scope(|| {
+    ///     (0..100).scan(Seq::empty(), |seq, i| {
+    ///         *seq = seq.push_back(i);
+    ///         Some(seq.clone())
+    ///     }).collect()
+    /// });
+    /// assert!(heap.allocator().allocated() > 0);
+    /// // The arena's blocks are freed when the heap, and the last node allocated from it, have been dropped.
+    /// drop(versions);
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn scope<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        struct Restore(*const Source);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let _ = CURRENT.try_with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(self.source.as_ptr())));
+        f()
+    }
+
+    fn source(&self) -> &Source {
+        unsafe { self.source.as_ref() }
+    }
+}
+
+impl<A: NodeAlloc> Clone for NodeHeap<A> {
+    fn clone(&self) -> NodeHeap<A> {
+        self.source().count.increment();
+        NodeHeap {
+            source: self.source,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<A> Drop for NodeHeap<A> {
+    fn drop(&mut self) {
+        unsafe { Source::release(self.source.as_ptr()) }
+    }
+}
+
+/// An allocator, shared by the handles to it and the nodes allocated from it.
+struct Source {
+    /// The number of handles, and of nodes that have not been freed.
+    count: Count,
+    alloc: Box<dyn NodeAlloc>,
+}
+
+impl Source {
+    /// Drop a handle or node, and the source, if it was the last.
+    unsafe fn release(source: *mut Source) {
+        if (*source).count.decrement() {
+            drop(Box::from_raw(source))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// The source of the current `NodeHeap::scope` on this thread, or null.
+    static CURRENT: Cell<*const Source> = const { Cell::new(ptr::null()) }
+}
+
+/// The source to allocate a new node from, or null for the global allocator.
+#[cfg(feature = "std")]
+fn current_source() -> *const Source {
+    CURRENT.try_with(|current| current.get()).unwrap_or(ptr::null())
+}
+
+/// Without the standard library, there are no scopes.
+#[cfg(not(feature = "std"))]
+fn current_source() -> *const Source {
+    ptr::null()
+}
+
+/// A reference count, which is only updated atomically if it was created atomic.
+///
+/// The count of a local value is only used on one thread, so plain loads and stores are enough, and need no read-modify-write support from the target.
+struct Count {
+    n: AtomicUsize,
+    #[cfg(feature = "sync")]
+    atomic: bool,
+}
+
+impl Count {
+    fn new(n: usize, atomic: bool) -> Count {
+        // Without the `sync` feature, there are no atomic thunks.
+        #[cfg(not(feature = "sync"))]
+        debug_assert!(!atomic);
+        Count {
+            n: AtomicUsize::new(n),
+            #[cfg(feature = "sync")]
+            atomic,
+        }
+    }
+
+    fn get(&self) -> usize {
+        self.n.load(Ordering::Acquire)
+    }
+
+    fn increment(&self) {
+        #[cfg(feature = "sync")]
+        {
+            if self.atomic {
+                if self.n.fetch_add(1, Ordering::Relaxed) > isize::MAX as usize {
+                    self.n.fetch_sub(1, Ordering::Relaxed);
+                    panic!("Reference count overflow")
+                }
+                return
+            }
+        }
+        let n = self.n.load(Ordering::Relaxed);
+        if n == usize::MAX {
+            panic!("Reference count overflow")
+        }
+        self.n.store(n + 1, Ordering::Relaxed)
+    }
+
+    /// Decrement the count, returning true if this was the last reference.
+    fn decrement(&self) -> bool {
+        #[cfg(feature = "sync")]
+        {
+            if self.atomic {
+                if self.n.fetch_sub(1, Ordering::Release) != 1 {
+                    return false
+                }
+                // Everything done through the other references happens before the value is dropped.
+                atomic::fence(Ordering::Acquire);
+                return true
+            }
+        }
+        let n = self.n.load(Ordering::Relaxed) - 1;
+        self.n.store(n, Ordering::Relaxed);
+        n == 0
+    }
+}
+
+/// A reference-counted pointer to a value allocated from the current heap, like an `Rc`, or an `Arc` if it was allocated atomic.
+pub(crate) struct Counted<T: ?Sized> {
+    ptr: NonNull<Inner<T>>,
+    marker: PhantomData<Inner<T>>,
+}
+
+/// The allocation behind a `Counted`.
+struct Inner<T: ?Sized> {
+    count: Count,
+    /// The source the allocation came from, or null for the global allocator.
+    source: *const Source,
+    value: T,
+}
+
+impl Counted<dyn Part> {
+    /// Allocate `val` from the current heap, or from the global allocator if its count is `atomic`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer forgets the lifetimes in `T`, which must outlive any use of it; see `Owned`. If the count is not `atomic`, the pointer and its clones must stay on the current thread.
+    pub(crate) unsafe fn new<'a, T: Part + 'a>(val: T, atomic: bool) -> Counted<dyn Part> {
+        let layout = Layout::new::<Inner<T>>();
+        let source = if atomic { ptr::null() } else { current_source() };
+        let ptr = if source.is_null() {
+            alloc(layout)
+        } else {
+            (*source).count.increment();
+            (*source).alloc.alloc(layout)
+        } as *mut Inner<T>;
+        if ptr.is_null() {
+            handle_alloc_error(layout)
+        }
+        ptr.write(Inner {
+            count: Count::new(1, atomic),
+            source,
+            value: val,
+        });
+        let ptr: *mut Inner<dyn Part + 'a> = ptr;
+        Counted {
+            ptr: NonNull::new_unchecked(mem::transmute::<*mut Inner<dyn Part + 'a>, *mut Inner<dyn Part>>(ptr)),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Counted<T> {
+    fn inner(&self) -> &Inner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    /// A mutable reference to the value, if there are no other pointers to it.
+    pub(crate) fn get_mut(this: &mut Counted<T>) -> Option<&mut T> {
+        if this.inner().count.get() == 1 {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn strong_count(this: &Counted<T>) -> usize {
+        this.inner().count.get()
+    }
+
+    pub(crate) fn as_ptr(this: &Counted<T>) -> *const T {
+        unsafe { ptr::addr_of!((*this.ptr.as_ptr()).value) }
+    }
+}
+
+impl<T: ?Sized> Deref for Counted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: ?Sized> Clone for Counted<T> {
+    fn clone(&self) -> Counted<T> {
+        self.inner().count.increment();
+        Counted {
+            ptr: self.ptr,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Counted<T> {
+    fn drop(&mut self) {
+        if !self.inner().count.decrement() {
+            return
+        }
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            let source = self.inner().source as *mut Source;
+            ptr::drop_in_place(self.ptr.as_ptr());
+            let ptr = self.ptr.as_ptr() as *mut u8;
+            if source.is_null() {
+                dealloc(ptr, layout)
+            } else {
+                (*source).alloc.dealloc(ptr, layout);
+                Source::release(source)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::{NodeAlloc, NodeHeap, Arena, Global};
+    use core::alloc::Layout;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use seq::Seq;
+    #[cfg(feature = "sync")]
+    use seq::SyncSeq;
+
+    /// Counts the blocks allocated from the global allocator that have not been freed, and whether it has been dropped.
+    struct Tracked {
+        live: Arc<AtomicUsize>,
+        dropped: Arc<AtomicUsize>,
+    }
+
+    unsafe impl NodeAlloc for Tracked {
+        fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.live.fetch_add(1, Ordering::SeqCst);
+            Global.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.live.fetch_sub(1, Ordering::SeqCst);
+            Global.dealloc(ptr, layout)
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.dropped.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn tracked() -> (NodeHeap<Tracked>, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let live = Arc::new(AtomicUsize::new(0));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let heap = NodeHeap::new(Tracked { live: live.clone(), dropped: dropped.clone() });
+        (heap, live, dropped)
+    }
+
+    #[test]
+    fn test_scope_should_allocate_from_heap() {
+        let heap = NodeHeap::new(Arena::new());
+        let seq: Seq<u32> = heap.scope(|| (0..1000).collect());
+        let allocated = heap.allocator().allocated();
+        assert!(allocated > 0);
+        let other: Seq<u32> = (0..1000).collect();
+        assert_eq!(heap.allocator().allocated(), allocated);
+        assert_eq!(seq, other);
+    }
+
+    #[test]
+    fn test_heap_should_outlive_nodes() {
+        let (heap, live, dropped) = tracked();
+        let seq: Seq<u32> = heap.scope(|| (0..100).fold(Seq::empty(), |seq, i| seq.push_back(i)));
+        drop(heap);
+        assert!(live.load(Ordering::SeqCst) > 0);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        assert_eq!(seq.iter().cloned().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+        drop(seq);
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_nested_scopes_should_restore_heap() {
+        let (outer, outer_live, _) = tracked();
+        let (inner, inner_live, _) = tracked();
+        let seqs = outer.scope(|| {
+            let a: Seq<u32> = Seq::singleton(1);
+            let n = outer_live.load(Ordering::SeqCst);
+            let b: Seq<u32> = inner.scope(|| Seq::singleton(2));
+            assert_eq!(outer_live.load(Ordering::SeqCst), n);
+            assert!(inner_live.load(Ordering::SeqCst) > 0);
+            let c: Seq<u32> = Seq::singleton(3);
+            assert!(outer_live.load(Ordering::SeqCst) > n);
+            (a, b, c)
+        });
+        let n = outer_live.load(Ordering::SeqCst);
+        let d: Seq<u32> = Seq::singleton(4);
+        assert_eq!(outer_live.load(Ordering::SeqCst), n);
+        drop((seqs, d));
+        assert_eq!(outer_live.load(Ordering::SeqCst), 0);
+        assert_eq!(inner_live.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_panic_should_end_scope() {
+        let (heap, live, _) = tracked();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            heap.scope(|| -> () { panic!("Muahahahah") })
+        }));
+        assert!(result.is_err());
+        let seq: Seq<u32> = Seq::singleton(1);
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        drop(seq);
+    }
+
+    #[test]
+    fn test_arena_should_align_blocks() {
+        let arena = Arena::with_block_size(64);
+        let mut ptrs = Vec::new();
+        for &(size, align) in &[(1, 1), (8, 8), (3, 1), (16, 16), (100, 4), (32, 64), (1, 1)] {
+            let ptr = arena.alloc(Layout::from_size_align(size, align).unwrap());
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % align, 0);
+            unsafe { ptr.write_bytes(0xff, size) };
+            ptrs.push(ptr);
+        }
+        assert_eq!(arena.allocated(), 1 + 8 + 3 + 16 + 100 + 32 + 1);
+        assert!(arena.blocks() > 1);
+    }
+
+    #[test]
+    #[cfg(feature = "sync")]
+    fn test_sync_nodes_should_not_use_heap() {
+        use std::thread;
+        let (heap, live, dropped) = tracked();
+        let seq: SyncSeq<u32> = heap.scope(|| (0..100).fold(SyncSeq::empty_sync(), |seq, i| seq.push_front(i)));
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        drop(heap);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        thread::spawn(move || assert_eq!(seq.len(), 100)).join().unwrap();
+    }
+}
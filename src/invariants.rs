@@ -0,0 +1,93 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use lazy::{Thunk, Sharing, Peek, LazyError};
+
+/// A broken invariant of a finger tree, found by `Seq::check_invariants`.
+///
+/// The levels of the spine are numbered from 0 at the root, and each level holds nodes one higher than the level above it.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum InvariantError {
+    /// A `Deep` level, or a node at the given level, caches a measure that is not the sum of the measures of its parts.
+    Measure { level: usize },
+    /// The leaves below a digit at the given level are not all at the same depth, or not at the depth for that level.
+    Depth { level: usize },
+    /// A leaf of a sequence holds no elements.
+    EmptyLeaf,
+    /// A thunk is still being evaluated, or is being forced by its own producer.
+    InProgress,
+    /// A thunk could not be evaluated, because an earlier evaluation of it panicked.
+    Poisoned,
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvariantError::Measure { level } =>
+                write!(f, "Cached measure does not match its parts, at level {}", level),
+            InvariantError::Depth { level } =>
+                write!(f, "Leaves are at the wrong depth, at level {}", level),
+            InvariantError::EmptyLeaf =>
+                write!(f, "Leaf with no elements"),
+            InvariantError::InProgress =>
+                write!(f, "Thunk stuck in evaluation"),
+            InvariantError::Poisoned =>
+                write!(f, "Thunk poisoned: an earlier evaluation of this thunk panicked"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for InvariantError {}
+
+/// Force `thunk`, checking first that it is not already being evaluated.
+pub fn force<T, K: Sharing>(mut thunk: &Thunk<T, K>) -> Result<&T, InvariantError> {
+    loop {
+        match thunk.peek() {
+            Peek::Evaluated(val) => return Ok(val),
+            Peek::Redirect(target) => thunk = target,
+            Peek::Running => return Err(InvariantError::InProgress),
+            Peek::Poisoned => return Err(InvariantError::Poisoned),
+            Peek::Unevaluated => return match thunk.try_force() {
+                Ok(val) => Ok(val),
+                Err(LazyError::Poisoned) => Err(InvariantError::Poisoned),
+                Err(LazyError::Recursive) => Err(InvariantError::InProgress),
+            },
+        }
+    }
+}
+
+// Every thunk is evaluated when it is created with the `strict` feature, so no thunk can be running or poisoned.
+#[cfg(all(test, not(feature = "strict")))]
+mod test {
+    use super::{force, InvariantError};
+    use lazy::{Thunk, value};
+    use std::rc::Rc;
+    use std::sync::Mutex;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_force_running_should_fail() {
+        let slot: Rc<Mutex<Option<Thunk<bool>>>> = Rc::new(Mutex::new(None));
+        let slot_clone = slot.clone();
+        let val = lazy!({
+            let this = slot_clone.lock().unwrap().clone().unwrap();
+            value(force(&this).err() == Some(InvariantError::InProgress))
+        });
+        *slot.lock().unwrap() = Some(val.clone());
+        assert_eq!(force(&val), Ok(&true));
+        *slot.lock().unwrap() = None;
+    }
+
+    #[test]
+    fn test_force_poisoned_should_fail() {
+        let val: Thunk<u32> = lazy!(panic!("Muahahahah"));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| val.force()));
+        assert_eq!(force(&val), Err(InvariantError::Poisoned));
+        let val: Thunk<u32> = lazy!(panic!("Muahahahah"));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| val.force()));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| val.force()));
+        assert_eq!(force(&val), Err(InvariantError::Poisoned));
+    }
+}
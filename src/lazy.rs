@@ -1,12 +1,24 @@
 // This code is based on code from Jonathan Reem's rust-lazy library (https://github.com/reem/rust-lazy)
 
-use std::ops::Deref;
-use std::cell::UnsafeCell;
-use std::rc::Rc;
-use std::mem;
-use std::fmt;
+//! Lazily evaluated values, which are shared between the nodes of sequences.
 
-use self::Inner::{Evaluated, EvaluationInProgress, Unevaluated, Redirect};
+use core::ops::Deref;
+use core::fmt;
+use core::mem::{self, ManuallyDrop};
+use core::marker::PhantomData;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::thread::{self, ThreadId};
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+
+use heap::Counted;
+
+use self::Inner::{Evaluated, Redirect, Poisoned};
+use self::State::{Pending, Running, Failed, Done};
 
 /// Helper macro for writing lazy expressions
 ///
@@ -21,49 +33,239 @@ use self::Inner::{Evaluated, EvaluationInProgress, Unevaluated, Redirect};
 /// assert_eq!(*thunk, 7u32);
 /// # }
 /// ```
-#[doc(hidden)]
-#[macro_export]
+///
+/// A producer that forces other thunks should name them with `after`, so that they are forced first, without recursion; see `Thunk::after`.
+///
+/// ```rust,ignore
+/// let sum = lazy!{ after x, y;
+///     value(*x + *y)
+/// };
+/// ```
+///
+/// The producer may borrow data, as long as it meets the requirements of `Thunk::new_unchecked`.
 macro_rules! lazy {
-    ($($e: stmt);*) => {
-        $crate::lazy::Thunk::new(move || { $($e);* })
-    }
+    (after $d: ident; $($body: tt)*) => {{
+        let deps = $d;
+        let producer = $crate::lazy::producer_after(&deps, move |$d| { $($body)* });
+        unsafe { $crate::lazy::Thunk::after(deps, producer) }
+    }};
+    (after $d0: ident, $d1: ident; $($body: tt)*) => {{
+        let deps = ($d0, $d1);
+        let producer = $crate::lazy::producer_after(&deps, move |($d0, $d1)| { $($body)* });
+        unsafe { $crate::lazy::Thunk::after(deps, producer) }
+    }};
+    ($($body: tt)*) => {{
+        let producer = move || { $($body)* };
+        unsafe { $crate::lazy::Thunk::new_unchecked(producer) }
+    }}
 }
 
-#[doc(hidden)]
-#[macro_export]
 macro_rules! lazy_val {
-    ($($e: stmt);*) => {
-        $crate::lazy::Thunk::new(move || { value({$($e);*}) })
+    (after $($d: ident),+; $($body: tt)*) => {
+        lazy!(after $($d),+; value({$($body)*}))
+    };
+    ($($body: tt)*) => {
+        lazy!(value({$($body)*}))
     }
 }
 
-#[doc(hidden)]
-#[macro_export]
-macro_rules! lazy_redirect {
-    ($($e: stmt);*) => {
-        $crate::lazy::Thunk::new(move || { redirect({$($e);*}) })
-    }
+/// Fix the argument type of a producer passed to `Thunk::after`, so that `lazy!` can build it outside of an `unsafe` block.
+pub(crate) fn producer_after<D, F, T, K: Sharing>(_deps: &D, producer: F) -> F
+    where F: FnOnce(D) -> ThunkResult<T, K> {
+    producer
 }
 
-pub fn strict<T>(v: T) -> Thunk<T> {
+/// An already evaluated thunk.
+pub fn strict<T, K: Sharing>(v: T) -> Thunk<T, K> {
     Thunk::evaluated(v)
 }
 
-pub fn redirect<T>(t: Thunk<T>) -> ThunkResult<T> {
+/// Produce the value of another thunk.
+pub fn redirect<T, K: Sharing>(t: Thunk<T, K>) -> ThunkResult<T, K> {
     ThunkResult::Redirect(t)
 }
 
-pub fn value<T>(v: T) -> ThunkResult<T> {
+/// Produce a value.
+pub fn value<T, K: Sharing>(v: T) -> ThunkResult<T, K> {
     ThunkResult::Value(v)
 }
 
+/// How the thunks of a sequence are shared: between clones on one thread, with `Local`, or between threads as well, with `Atomic`.
+///
+/// `Seq<T>` is `Seq<T, Local>`, and `SyncSeq<T>` is `Seq<T, Atomic>`. The two kinds of thunk can not be mixed in one sequence.
+pub trait Sharing: kind::Kind + fmt::Debug + 'static {}
+
+/// Thunks that are only used on one thread, whose reference counts and state are not synchronized.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum Local {}
+
+/// Thunks that may be shared between threads, whose reference counts and state are synchronized.
+#[cfg(feature = "sync")]
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum Atomic {}
+
+impl Sharing for Local {}
+
+#[cfg(feature = "sync")]
+impl Sharing for Atomic {}
+
+/// Values that may be stored in, or captured by, a thunk shared in the way `K` describes.
+///
+/// Every type is `Shared<Local>`. Thunks that are `Atomic` are shared between threads, so `Shared<Atomic>` requires `Send + Sync`.
+pub trait Shared<K: Sharing = Local> {}
+
+impl<T: ?Sized> Shared<Local> for T {}
+
+#[cfg(feature = "sync")]
+impl<T: Send + Sync + ?Sized> Shared<Atomic> for T {}
+
+/// The cells a thunk is built from, for each kind of `Sharing`.
+mod kind {
+    use core::cell::{RefCell, OnceCell};
+    #[cfg(feature = "sync")]
+    use std::sync::{Mutex, OnceLock};
+
+    pub trait Kind: Sized {
+        /// Holds the value once it has been computed.
+        type Once<T>: Once<T>;
+        /// Holds the state of evaluation.
+        type Lock<T>: Lock<T>;
+        /// Whether reference counts are updated atomically.
+        const ATOMIC: bool;
+    }
+
+    pub trait Once<T> {
+        fn new() -> Self;
+        fn get(&self) -> Option<&T>;
+        fn set(&self, val: T) -> Result<(), T>;
+        fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T;
+        fn get_mut(&mut self) -> Option<&mut T>;
+        fn take(&mut self) -> Option<T>;
+    }
+
+    pub trait Lock<T> {
+        fn new(val: T) -> Self;
+        fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R;
+    }
+
+    impl Kind for super::Local {
+        type Once<T> = OnceCell<T>;
+        type Lock<T> = RefCell<T>;
+        const ATOMIC: bool = false;
+    }
+
+    #[cfg(feature = "sync")]
+    impl Kind for super::Atomic {
+        type Once<T> = OnceLock<T>;
+        type Lock<T> = Mutex<T>;
+        const ATOMIC: bool = true;
+    }
+
+    impl<T> Once<T> for OnceCell<T> {
+        fn new() -> OnceCell<T> { OnceCell::new() }
+        fn get(&self) -> Option<&T> { OnceCell::get(self) }
+        fn set(&self, val: T) -> Result<(), T> { OnceCell::set(self, val) }
+        fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T { OnceCell::get_or_init(self, f) }
+        fn get_mut(&mut self) -> Option<&mut T> { OnceCell::get_mut(self) }
+        fn take(&mut self) -> Option<T> { OnceCell::take(self) }
+    }
+
+    #[cfg(feature = "sync")]
+    impl<T> Once<T> for OnceLock<T> {
+        fn new() -> OnceLock<T> { OnceLock::new() }
+        fn get(&self) -> Option<&T> { OnceLock::get(self) }
+        fn set(&self, val: T) -> Result<(), T> { OnceLock::set(self, val) }
+        fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T { OnceLock::get_or_init(self, f) }
+        fn get_mut(&mut self) -> Option<&mut T> { OnceLock::get_mut(self) }
+        fn take(&mut self) -> Option<T> { OnceLock::take(self) }
+    }
+
+    impl<T> Lock<T> for RefCell<T> {
+        fn new(val: T) -> RefCell<T> {
+            RefCell::new(val)
+        }
+
+        fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+            f(&mut self.borrow_mut())
+        }
+    }
+
+    #[cfg(feature = "sync")]
+    impl<T> Lock<T> for Mutex<T> {
+        fn new(val: T) -> Mutex<T> {
+            Mutex::new(val)
+        }
+
+        fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+            match self.lock() {
+                Ok(mut val) => f(&mut val),
+                Err(err) => f(&mut err.into_inner()),
+            }
+        }
+    }
+}
+
+use self::kind::{Once, Lock};
+
 /// A sometimes-cleaner name for a lazily evaluated value.
-pub type Lazy<T> = Thunk<T>;
+pub type Lazy<T, K = Local> = Thunk<T, K>;
 
 /// A lazily evaluated value.
-pub struct Thunk<T> (UnsafeCell<Rc<UnsafeCell<Inner<T>>>>);
+///
+/// The value is shared between clones of the thunk, and is computed at most once, the first time one of them is forced.
+///
+/// Dropping a thunk does not recurse into the thunks it holds, whether in its value or in its unevaluated producer, so arbitrarily long chains of thunks can be dropped with a small stack. This needs the `std` feature; without it, dropping recurses.
+pub struct Thunk<T, K: Sharing = Local> (Owned<Cell<T, K>, K>);
+
+struct Cell<T, K: Sharing> {
+    inner: K::Once<Inner<T, K>>,
+    state: K::Lock<State<T, K>>,
+}
+
+// The producer of an atomic thunk, and everything it holds, is `Shared<Atomic>`.
+#[cfg(feature = "sync")]
+unsafe impl<T: Send + Sync> Send for Cell<T, Atomic> {}
 
-impl<T> Thunk<T> {
+#[cfg(feature = "sync")]
+unsafe impl<T: Send + Sync> Sync for Cell<T, Atomic> {}
+
+/// How far evaluation of a thunk has got.
+enum State<T, K: Sharing> {
+    /// The producer has not been run yet. With the `strict` feature, thunks are evaluated when they are created, so this is never constructed.
+    #[cfg_attr(feature = "strict", allow(dead_code))]
+    Pending(Producer<ThunkResult<T, K>>),
+    /// The producer is being run, on the given thread.
+    Running(ThreadId),
+    /// The producer panicked.
+    Failed,
+    /// The value has been stored.
+    Done,
+}
+
+/// The reason a thunk could not be evaluated.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum LazyError {
+    /// An earlier attempt to evaluate the thunk panicked, so there is no value, and never will be.
+    Poisoned,
+    /// The thunk was forced while it was being evaluated, on the same thread.
+    Recursive,
+}
+
+impl fmt::Display for LazyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LazyError::Poisoned =>
+                write!(f, "Thunk poisoned: an earlier evaluation of this thunk panicked"),
+            LazyError::Recursive =>
+                write!(f, "Thunk forced recursively: a thunk tried to force itself while being evaluated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LazyError {}
+
+impl<T, K: Sharing> Thunk<T, K> {
     /// Create a lazily evaluated value from a proc that returns that value.
     ///
     /// You can construct Thunk's manually using this, but the lazy! macro
@@ -71,116 +273,535 @@ impl<T> Thunk<T> {
     ///
     /// ```rust,ignore
     /// # use immutable_seq::lazy::{Thunk, value};
-    /// let expensive = Thunk::new(|| { println!("Evaluated!"); value(7u32) });
+    /// let expensive: Thunk<u32> = Thunk::new(|| { println!("Evaluated!"); value(7u32) });
     /// assert_eq!(*expensive, 7u32); // "Evaluated!" gets printed here.
     /// assert_eq!(*expensive, 7u32); // Nothing printed.
     /// ```
-    pub fn new<F>(producer: F) -> Thunk<T>
-    where F: FnOnce() -> ThunkResult<T> + 'static {
-        Thunk(UnsafeCell::new(Rc::new(UnsafeCell::new(Unevaluated(Producer::new(producer))))))
+    pub fn new<F>(producer: F) -> Thunk<T, K>
+    where F: FnOnce() -> ThunkResult<T, K> + Shared<K> + 'static {
+        // Nothing captured by a `'static` producer can expire.
+        unsafe { Thunk::new_unchecked(producer) }
+    }
+
+    /// Create a lazily evaluated value from a proc that may borrow data.
+    ///
+    /// # Safety
+    ///
+    /// Everything `producer` captures must stay valid for as long as a value of type `T` could be used; that is, `T` must not outlive anything the producer borrows. The producers in this crate capture nodes of the same elements as the value they produce, together with `'static` data.
+    ///
+    /// For an `Atomic` thunk, everything `producer` captures must also be `Shared<Atomic>`, which the nodes of a sequence are when its elements are, since the thunk may be forced on another thread. This is not checked here, so that code generic over `K` need not prove it for every type it captures; the public constructors of `Seq` check it for the elements and functions they are given.
+    #[cfg(not(feature = "strict"))]
+    pub(crate) unsafe fn new_unchecked<F>(producer: F) -> Thunk<T, K>
+    where F: FnOnce() -> ThunkResult<T, K> {
+        Thunk::from_cell(Cell {
+            inner: K::Once::new(),
+            state: Lock::new(Pending(Producer::new(Box::new(producer)))),
+        })
+    }
+
+    /// Create a value from a proc that returns that value.
+    ///
+    /// With the `strict` feature, the proc is run immediately, and a redirect
+    /// returns the thunk it redirects to, so no work is ever deferred.
+    #[cfg(feature = "strict")]
+    pub(crate) unsafe fn new_unchecked<F>(producer: F) -> Thunk<T, K>
+    where F: FnOnce() -> ThunkResult<T, K> {
+        match producer() {
+            ThunkResult::Value(x) => Thunk::evaluated(x),
+            ThunkResult::Redirect(t) => t,
+        }
+    }
+
+    /// Create a lazily evaluated value from a proc that forces the thunks in `deps`.
+    ///
+    /// Forcing the result forces any of `deps` that have not been evaluated yet first, along with their own dependencies, using a loop rather than recursion. A long chain of thunks, each of which forces the one before it, can then be forced with a small stack.
+    ///
+    /// # Safety
+    ///
+    /// The same as for `new_unchecked`, for both `deps` and `producer`.
+    #[cfg(not(feature = "strict"))]
+    pub(crate) unsafe fn after<D, F>(deps: D, producer: F) -> Thunk<T, K>
+    where D: Dependencies,
+          F: FnOnce(D) -> ThunkResult<T, K> {
+        Thunk::from_cell(Cell {
+            inner: K::Once::new(),
+            state: Lock::new(Pending(Producer::new(Box::new(After {
+                deps,
+                producer,
+            })))),
+        })
+    }
+
+    /// Create a value from a proc that forces the thunks in `deps`.
+    ///
+    /// With the `strict` feature, `deps` have already been evaluated, and this is the same as `new_unchecked`.
+    #[cfg(feature = "strict")]
+    pub(crate) unsafe fn after<D, F>(deps: D, producer: F) -> Thunk<T, K>
+    where D: Dependencies,
+          F: FnOnce(D) -> ThunkResult<T, K> {
+        Thunk::new_unchecked(move || producer(deps))
     }
 
     /// Create a new, evaluated, thunk from a value.
-    pub fn evaluated(val: T) -> Thunk<T> {
-        Thunk(UnsafeCell::new(Rc::new(UnsafeCell::new(Evaluated(val)))))
+    pub fn evaluated(val: T) -> Thunk<T, K> {
+        let inner = K::Once::new();
+        let _ = inner.set(Evaluated(val));
+        Thunk::from_cell(Cell {
+            inner,
+            state: Lock::new(Done),
+        })
+    }
+
+    fn from_cell(cell: Cell<T, K>) -> Thunk<T, K> {
+        Thunk(Owned::new(cell))
     }
 
     /// Force evaluation of a thunk.
+    ///
+    /// Panics if the thunk can not be evaluated; see `try_force`.
     pub fn force(&self) {
-        loop {
-            match *self.inner() {
-                Evaluated(_) => return,
-                EvaluationInProgress => {
-                    panic!("Thunk::force called recursively. (A Thunk tried to force itself while trying to force itself).")
-                },
-                Redirect(ref t) => {
-                    self.redirect(t.clone());
-                    continue;
-                },
-                Unevaluated(_) => ()
-            };
-            break;
+        if let Err(err) = self.try_force() {
+            panic!("{}", err)
         }
+    }
 
-        match mem::replace(self.inner(), EvaluationInProgress) {
-            Unevaluated(producer) => {
-                *self.inner() = EvaluationInProgress;
-                match producer.invoke() {
-                    ThunkResult::Value(x) =>
-                        *self.inner() = Evaluated(x),
-                    ThunkResult::Redirect(t) => {
-                        t.force();
-                        *self.inner() = Redirect(t.clone());
-                        self.redirect(t);
+    /// Force evaluation of a thunk, and get a reference to the value.
+    ///
+    /// If the producer panics, the panic is propagated, and the thunk is poisoned: forcing it again returns `Err(LazyError::Poisoned)`, rather than running the producer again. Forcing a thunk from its own producer returns `Err(LazyError::Recursive)`.
+    ///
+    /// Chains of redirects, and of dependencies declared with `after`, are followed in a loop, so forcing uses a bounded amount of stack however long they are.
+    pub fn try_force(&self) -> Result<&T, LazyError> {
+        self.0.get()
+    }
+
+    /// Force evaluation, and get a mutable reference to the value if no other
+    /// thunk shares it.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.force();
+        while let Some(Redirect(t)) = self.0.inner.get() {
+            let target = t.clone();
+            *self = target;
+        }
+        match Owned::get_mut(&mut self.0) {
+            Some(cell) => match cell.inner.get_mut() {
+                Some(&mut Evaluated(ref mut val)) => Some(val),
+                _ => unreachable!(),
+            },
+            None => None,
+        }
+    }
+
+    /// Force evaluation, and get a mutable reference to the value, first
+    /// replacing `self` with an evaluated copy if the value is shared.
+    ///
+    /// This is the thunk analogue of `Rc::make_mut`.
+    pub fn make_mut(&mut self) -> &mut T
+        where T: Clone
+    {
+        if self.get_mut().is_none() {
+            *self = Thunk::evaluated((**self).clone());
+        }
+        self.get_mut().unwrap()
+    }
+
+    /// Force evaluation, and take the value out if no other thunk shares it.
+    ///
+    /// If the value is shared, `self` is returned unchanged.
+    pub fn try_unwrap(mut self) -> Result<T, Thunk<T, K>> {
+        if self.get_mut().is_none() {
+            return Err(self)
+        }
+        match Owned::get_mut(&mut self.0).and_then(|cell| cell.inner.take()) {
+            Some(Evaluated(val)) => Ok(val),
+            _ => unreachable!(),
+        }
+    }
+
+    /// How far the thunk has been evaluated, without forcing it.
+    pub(crate) fn peek<'a>(&'a self) -> Peek<'a, T, K> {
+        match self.0.inner.get() {
+            Some(inner) => match *inner {
+                Evaluated(ref val) => Peek::Evaluated(val),
+                Redirect(ref t) => Peek::Redirect(t),
+                Poisoned => Peek::Poisoned,
+            },
+            None => self.0.with_state(|state| match *state {
+                Running(_) => Peek::Running,
+                _ => Peek::Unevaluated,
+            }),
+        }
+    }
+
+    /// The address of the shared value, which identifies the thunk and its clones.
+    pub(crate) fn addr(&self) -> usize {
+        Counted::as_ptr(&self.0.part.0) as *const () as usize
+    }
+}
+
+/// The state of a thunk, as seen by `Thunk::peek`.
+pub(crate) enum Peek<'a, T: 'a, K: Sharing> {
+    /// The producer has not been run, or panicked and the thunk has not been forced since.
+    Unevaluated,
+    /// The producer is running.
+    Running,
+    Evaluated(&'a T),
+    Redirect(&'a Thunk<T, K>),
+    /// The producer panicked.
+    Poisoned,
+}
+
+impl<T, K: Sharing> Cell<T, K> {
+    /// Evaluate the cell, following redirects, and get the value.
+    fn get(&self) -> Result<&T, LazyError> {
+        let mut cell = self;
+        loop {
+            let inner = match cell.inner.get() {
+                Some(inner) => inner,
+                None => {
+                    // Once cells do not support re-entrant initialization, so
+                    // recursion has to be caught before trying to initialize.
+                    let current = current_thread();
+                    let dependency = cell.with_state(|state| match *state {
+                        Running(id) if id == current => Err(LazyError::Recursive),
+                        Pending(ref producer) => Ok(producer.inner.dependency()),
+                        _ => Ok(None),
+                    })?;
+                    if let Some(dependency) = dependency {
+                        force_dependencies(dependency);
                     }
+                    cell.inner.get_or_init(|| cell.evaluate())
                 }
-            }
-            _ => {
-                let x = 42;
-                println!("thats not good {}",x);
-                // unsafe { debug_unreachable!() }   
+            };
+            match *inner {
+                Evaluated(ref val) => return Ok(val),
+                Redirect(ref t) => cell = &t.0,
+                Poisoned => return Err(LazyError::Poisoned),
             }
         }
     }
 
-    fn inner(&self) -> &mut Inner<T> {
-        match *self {
-            Thunk(ref cell) => unsafe {
-                &mut *(**cell.get()).get()
-            }
+    /// The first unevaluated dependency of the producer, if it has not been run yet.
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        if self.inner.get().is_some() {
+            return None
         }
+        self.with_state(|state| match *state {
+            Pending(ref producer) => producer.inner.dependency(),
+            _ => None,
+        })
     }
 
-    fn rc(&self) -> &mut Rc<UnsafeCell<Inner<T>>> {
-        match *self {
-            Thunk(ref cell) => unsafe {
-                &mut *cell.get()
-            }
+    fn with_state<R, F: FnOnce(&mut State<T, K>) -> R>(&self, f: F) -> R {
+        self.state.with(f)
+    }
+
+    /// Run the producer, giving the value to store in `inner`.
+    ///
+    /// If the producer panics, the state is left as `Failed`, and the next
+    /// attempt to evaluate the thunk stores `Poisoned`.
+    fn evaluate(&self) -> Inner<T, K> {
+        let current = current_thread();
+        let producer = match self.with_state(|state| mem::replace(state, Running(current))) {
+            Pending(producer) => producer,
+            Failed => {
+                self.with_state(|state| *state = Done);
+                return Poisoned
+            },
+            Running(_) | Done => unreachable!(),
+        };
+        let guard = FailOnUnwind(self);
+        let inner = match producer.invoke() {
+            ThunkResult::Value(x) =>
+                Evaluated(x),
+            // The target is forced by `get`, after this cell has been
+            // initialized, so a chain of redirects does not recurse.
+            ThunkResult::Redirect(t) =>
+                Redirect(t.clone()),
+        };
+        mem::forget(guard);
+        self.with_state(|state| *state = Done);
+        inner
+    }
+}
+
+/// Force `first`, and the other dependencies of the cells being forced, deepest first.
+fn force_dependencies(first: Counted<dyn Part>) {
+    let mut waiting: Vec<Counted<dyn Part>> = Vec::new();
+    let mut next = Some(first);
+    while let Some(cell) = next.take().or_else(|| waiting.pop()) {
+        match cell.dependency() {
+            Some(dep) => {
+                waiting.push(cell);
+                next = Some(dep);
+            },
+            None => cell.force(),
+        }
+    }
+}
+
+/// A shared allocation of any type, held by an `Owned`.
+pub(crate) trait Part {
+    /// The first unevaluated dependency of a cell's producer.
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        None
+    }
+
+    /// Force a cell, ignoring any error, which is reported when the cell is next forced.
+    fn force(&self) {}
+}
+
+impl<T, K: Sharing> Part for Cell<T, K> {
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        Cell::dependency(self)
+    }
+
+    fn force(&self) {
+        let _ = self.get();
+    }
+}
+
+/// A part with a known type.
+pub(crate) trait Owner: Part {
+    /// The values that dropping the part may drop, or use.
+    type Owns;
+}
+
+impl<T, K: Sharing> Owner for Cell<T, K> {
+    // The producer only captures data that outlives `T`.
+    type Owns = T;
+}
+
+/// Thunks that a producer forces, which are given to the producer by `Thunk::after`.
+pub(crate) trait Dependencies {
+    /// The first of the thunks that has not been evaluated.
+    #[cfg(not(feature = "strict"))]
+    fn dependency(&self) -> Option<Counted<dyn Part>>;
+}
+
+impl<T, K: Sharing> Dependencies for Thunk<T, K> {
+    #[cfg(not(feature = "strict"))]
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        match self.0.inner.get() {
+            Some(_) => None,
+            None => Some(self.0.part()),
         }
     }
+}
 
-    fn redirect(&self, t: Thunk<T>) {
-        *self.rc() = t.rc().clone();
+impl<A: Dependencies, B: Dependencies> Dependencies for (A, B) {
+    #[cfg(not(feature = "strict"))]
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        self.0.dependency().or_else(|| self.1.dependency())
     }
 }
 
-impl<T> Deref for Thunk<T> {
+/// A producer which forces `deps`.
+#[cfg(not(feature = "strict"))]
+struct After<D, F> {
+    deps: D,
+    producer: F,
+}
+
+/// Marks a thunk as failed if its producer panics.
+struct FailOnUnwind<'a, T: 'a, K: Sharing>(&'a Cell<T, K>);
+
+impl<'a, T, K: Sharing> Drop for FailOnUnwind<'a, T, K> {
+    fn drop(&mut self) {
+        self.0.with_state(|state| *state = Failed);
+    }
+}
+
+impl<T, K: Sharing> Deref for Thunk<T, K> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        self.force();
-        match *self.inner()  {
-            Evaluated(ref val) => val,
-            _ => unreachable!(),
+        match self.try_force() {
+            Ok(val) => val,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl<T, K: Sharing> Clone for Thunk<T, K> {
+    /// Clones the reference to the value. Once a thunk has been redirected,
+    /// its clones refer directly to the thunk it was redirected to.
+    fn clone(&self) -> Thunk<T, K> {
+        match self.0.inner.get() {
+            Some(Redirect(t)) => t.clone(),
+            _ => Thunk(self.0.clone()),
+        }
+    }
+}
+
+/// A reference-counted pointer, like an `Rc<T>`, or an `Arc<T>` if `K` is `Atomic`, which the drop checker treats like a `Box<T>`.
+///
+/// The pointer is stored with its type, and lifetimes, erased, so dropping it does not run any generic code, and elements of a sequence can borrow data that is dropped at the same time as the sequence, as they can in a `Vec`. The `PhantomData` makes `Owned<T>` own the values in `T::Owns` as far as the drop checker is concerned, and keeps it invariant in `T`, like an `Rc` to an interior-mutable cell.
+///
+/// A local value is allocated from the current `NodeHeap`; see `heap`. Dropping the last pointer to it uses `release`, so it does not recurse.
+pub(crate) struct Owned<T: Owner, K: Sharing> {
+    part: Erased,
+    marker: PhantomData<(T::Owns, K)>,
+}
+
+/// A pointer to a part, whose lifetimes have been erased.
+///
+/// The drop checker ensures that an `Owned<T, K>` is dropped while its `T` is valid, and the last pointer to a part is dropped with it.
+struct Erased(ManuallyDrop<Counted<dyn Part>>);
+
+impl<T: Owner, K: Sharing> Owned<T, K> {
+    pub(crate) fn new(val: T) -> Owned<T, K> {
+        // The drop checker keeps `val` valid for as long as the part is used,
+        // and only an atomic part can be sent to another thread.
+        let part = unsafe { Counted::new(val, K::ATOMIC) };
+        Owned {
+            part: Erased(ManuallyDrop::new(part)),
+            marker: PhantomData,
         }
     }
+
+    /// A mutable reference to the value, if there are no other pointers to it.
+    pub(crate) fn get_mut(this: &mut Owned<T, K>) -> Option<&mut T> {
+        Counted::get_mut(&mut this.part.0).map(|part| unsafe { &mut *(part as *mut dyn Part as *mut T) })
+    }
+
+    /// Do `this` and `other` point to the same value?
+    #[cfg(test)]
+    pub(crate) fn ptr_eq(this: &Owned<T, K>, other: &Owned<T, K>) -> bool {
+        Counted::as_ptr(&this.part.0) as *const () == Counted::as_ptr(&other.part.0) as *const ()
+    }
+
+    /// Another pointer to the value, with its type erased.
+    fn part(&self) -> Counted<dyn Part> {
+        (*self.part.0).clone()
+    }
+}
+
+impl<T: Owner, K: Sharing> Deref for Owned<T, K> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*(Counted::as_ptr(&self.part.0) as *const T) }
+    }
 }
 
-impl<T> Clone for Thunk<T> {
-    fn clone(&self) -> Thunk<T> {
-        Thunk(UnsafeCell::new(self.rc().clone()))
+impl<T: Owner, K: Sharing> Clone for Owned<T, K> {
+    fn clone(&self) -> Owned<T, K> {
+        Owned {
+            part: Erased(ManuallyDrop::new(self.part())),
+            marker: PhantomData,
+        }
     }
 }
 
-impl<T> fmt::Debug for Thunk<T>
+#[cfg(feature = "sync")]
+unsafe impl<T: Owner + Send + Sync> Send for Owned<T, Atomic> {}
+
+#[cfg(feature = "sync")]
+unsafe impl<T: Owner + Send + Sync> Sync for Owned<T, Atomic> {}
+
+impl Drop for Erased {
+    fn drop(&mut self) {
+        // `self.0` is not used again.
+        let part = unsafe { ManuallyDrop::take(&mut self.0) };
+        // A shared part is not freed here, so dropping it can not recurse.
+        if Counted::strong_count(&part) == 1 {
+            release(part)
+        }
+    }
+}
+
+/// The thread forcing a thunk, which is recorded while its producer runs, to tell recursion from another thread forcing the same thunk.
+#[cfg(feature = "std")]
+fn current_thread() -> ThreadId {
+    thread::current().id()
+}
+
+/// Without the standard library, thunks are not shared between threads, so a thunk that is running is being forced by its own producer.
+#[cfg(not(feature = "std"))]
+#[derive(Clone,Copy,PartialEq,Eq)]
+struct ThreadId;
+
+#[cfg(not(feature = "std"))]
+fn current_thread() -> ThreadId {
+    ThreadId
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// Cells released while another release on this thread is dropping its cell.
+    ///
+    /// This is `None` unless a release is in progress.
+    static RELEASED: RefCell<Option<Vec<Counted<dyn Part>>>> = RefCell::new(None)
+}
+
+/// Drop the last reference to a cell.
+///
+/// Dropping a cell drops the thunks it holds, which would release their own cells, recursively. Instead, the outermost release on a thread queues the cells released while it is dropping its own, and then drops them one at a time, before it returns.
+#[cfg(feature = "std")]
+fn release(cell: Counted<dyn Part>) {
+    let first = RELEASED.try_with(move |released| {
+        match *released.borrow_mut() {
+            Some(ref mut queue) => {
+                queue.push(cell);
+                return None
+            },
+            ref mut queue => *queue = Some(Vec::new()),
+        }
+        Some(cell)
+    });
+    // If the thread-local queue has already been destroyed, the cell was
+    // dropped directly, along with the closure.
+    if let Ok(Some(cell)) = first {
+        let _draining = Draining;
+        drop(cell);
+        while let Some(cell) = RELEASED.with(|released| {
+            released.borrow_mut().as_mut().and_then(|queue| queue.pop())
+        }) {
+            drop(cell);
+        }
+    }
+}
+
+/// Drop the last reference to a cell.
+///
+/// Without the standard library there is no thread-local queue to put the cells it holds on, so they are dropped recursively.
+#[cfg(not(feature = "std"))]
+fn release(cell: Counted<dyn Part>) {
+    drop(cell)
+}
+
+/// Ends a release, dropping anything left in the queue if a drop panicked.
+#[cfg(feature = "std")]
+struct Draining;
+
+#[cfg(feature = "std")]
+impl Drop for Draining {
+    fn drop(&mut self) {
+        let rest = RELEASED.with(|released| released.borrow_mut().take());
+        drop(rest);
+    }
+}
+
+impl<T, K: Sharing> fmt::Debug for Thunk<T, K>
     where T: fmt::Debug
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_tuple("Thunk")
-            .field(self.inner())
-            .finish()
+        match self.0.inner.get() {
+            Some(inner) => f.debug_tuple("Thunk").field(inner).finish(),
+            None => write!(f, "Thunk(Unevaluated)"),
+        }
     }
 }
 
 /// Represents the two possible things a `Thunk<T>` can return: either a `T` value, or another `Thunk<T>`.
 #[derive(Debug)]
-pub enum ThunkResult<T> {
+pub enum ThunkResult<T, K: Sharing = Local> {
     Value(T),
-    Redirect(Thunk<T>)
+    Redirect(Thunk<T, K>)
 }
 
 struct Producer<T> {
-    inner: Box<Invoke<T>>
+    inner: Box<dyn Invoke<T>>
 }
 
 impl<T> fmt::Debug for Producer<T> {
@@ -190,11 +811,11 @@ impl<T> fmt::Debug for Producer<T> {
 }
 
 impl<T> Producer<T> {
-    fn new<F: FnOnce() -> T + 'static>(f: F) -> Producer<T> {
+    /// Forget the lifetime of the producer; see `Thunk::new_unchecked`.
+    #[cfg(not(feature = "strict"))]
+    unsafe fn new<'a>(inner: Box<dyn Invoke<T> + 'a>) -> Producer<T> {
         Producer {
-            inner: Box::new(move || {
-                f()
-            }) as Box<Invoke<T>>
+            inner: mem::transmute::<Box<dyn Invoke<T> + 'a>, Box<dyn Invoke<T>>>(inner),
         }
     }
 
@@ -203,17 +824,21 @@ impl<T> Producer<T> {
     }
 }
 
+/// The state of an evaluated thunk. A thunk whose producer redirected it to
+/// another thunk refers to that thunk, which has itself been evaluated.
 #[derive(Debug)]
-enum Inner<T> {
+enum Inner<T, K: Sharing> {
     Evaluated(T),
-    EvaluationInProgress,
-    Unevaluated(Producer<ThunkResult<T>>),
-    Redirect(Thunk<T>),
+    Redirect(Thunk<T, K>),
+    Poisoned,
 }
 
-#[doc(hidden)]
-pub trait Invoke<T> {
+pub(crate) trait Invoke<T> {
     fn invoke(self: Box<Self>) -> T;
+
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        None
+    }
 }
 
 impl<T, F> Invoke<T> for F
@@ -225,21 +850,47 @@ impl<T, F> Invoke<T> for F
     }
 }
 
+#[cfg(not(feature = "strict"))]
+impl<T, D, F> Invoke<T> for After<D, F>
+    where D: Dependencies,
+          F: FnOnce(D) -> T
+{
+    fn invoke(self: Box<Self>) -> T {
+        let After { deps, producer } = *self;
+        producer(deps)
+    }
+
+    fn dependency(&self) -> Option<Counted<dyn Part>> {
+        self.deps.dependency()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::{Thunk, value, redirect, strict};
+    #[cfg(not(feature = "strict"))]
+    use super::LazyError;
+    #[cfg(all(feature = "sync", not(feature = "strict")))]
+    use super::Atomic;
+    #[cfg(not(feature = "strict"))]
+    use std::rc::Rc;
     use std::sync::{Arc, Mutex};
     use std::thread;
+    #[cfg(not(feature = "strict"))]
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// The length of the chains of thunks used to check for recursion, shorter under Miri, which is too slow for the full length.
+    const LONG_CHAIN: usize = if cfg!(miri) { 1000 } else { 1_000_000 };
 
     #[test]
     fn test_thunk_should_evaluate_when_accessed() {
-        let val = lazy!(value(7));
+        let val: Thunk<_> = lazy!(value(7));
         assert_eq!(*val, 7);
     }
 
     #[test]
     fn test_thunk_should_evaluate_through_redirect() {
-        let val = lazy!(redirect(lazy!(value(7))));
+        let val: Thunk<_> = lazy!(redirect(lazy!(value(7))));
         assert_eq!(*val, 7);
     }
 
@@ -247,7 +898,7 @@ mod test {
     fn test_thunk_should_evaluate_just_once() {
         let counter = Arc::new(Mutex::new(0));
         let counter_clone = counter.clone();
-        let val = lazy!({
+        let val: Thunk<_> = lazy!({
             let mut data = counter.lock().unwrap();
             *data += 1;
             value(())
@@ -258,10 +909,11 @@ mod test {
     }
 
     #[test]
+    #[cfg(not(feature = "strict"))]
     fn test_thunk_should_not_evaluate_if_not_accessed() {
         let counter = Arc::new(Mutex::new(0));
         let counter_clone = counter.clone();
-        let _val = lazy!({
+        let _val: Thunk<_> = lazy!({
             let mut data = counter.lock().unwrap();
             *data += 1;
             value(())
@@ -269,9 +921,22 @@ mod test {
         assert_eq!(*counter_clone.lock().unwrap(), 0);
     }
 
+    #[test]
+    #[cfg(feature = "strict")]
+    fn test_thunk_should_evaluate_immediately_in_strict_mode() {
+        let counter = Arc::new(Mutex::new(0));
+        let counter_clone = counter.clone();
+        let _val: Thunk<_> = lazy!({
+            let mut data = counter.lock().unwrap();
+            *data += 1;
+            value(())
+        });
+        assert_eq!(*counter_clone.lock().unwrap(), 1);
+    }
+
     #[test]
     fn test_strict_should_produce_already_evaluated_thunk() {
-        let x = strict(10);
+        let x: Thunk<_> = strict(10);
         assert_eq!(*x, 10);
     }
 
@@ -298,6 +963,144 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_make_mut_should_copy_shared_value() {
+        let mut x: Thunk<i32> = lazy!(value(1));
+        let y = x.clone();
+        *x.make_mut() += 1;
+        assert_eq!(*x, 2);
+        assert_eq!(*y, 1);
+    }
+
+    #[test]
+    fn test_try_unwrap_should_fail_when_shared() {
+        let x: Thunk<_> = lazy!(redirect(strict(7)));
+        let y = x.clone();
+        let x = x.try_unwrap().unwrap_err();
+        drop(y);
+        assert_eq!(x.try_unwrap().ok(), Some(7));
+    }
+
+    #[test]
+    #[cfg(not(feature = "strict"))]
+    fn test_thunk_should_be_poisoned_after_panic() {
+        let counter = Arc::new(Mutex::new(0));
+        let counter_clone = counter.clone();
+        let val: Thunk<_> = lazy!({
+            *counter.lock().unwrap() += 1;
+            if true {
+                panic!("Muahahahah")
+            }
+            value(7)
+        });
+        let result = panic::catch_unwind(AssertUnwindSafe(|| *val));
+        assert!(result.is_err());
+        assert_eq!(val.try_force(), Err(LazyError::Poisoned));
+        assert_eq!(val.clone().try_force(), Err(LazyError::Poisoned));
+        assert_eq!(*counter_clone.lock().unwrap(), 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "strict"))]
+    #[should_panic(expected = "poisoned")]
+    fn test_poisoned_thunk_should_panic_when_dereferenced() {
+        let val: Thunk<u32> = lazy!(panic!("Muahahahah"));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| val.force()));
+        let _value: u32 = *val;
+    }
+
+    #[test]
+    #[cfg(not(feature = "strict"))]
+    fn test_redirect_to_poisoned_thunk_should_be_poisoned() {
+        let target: Thunk<u32> = lazy!(panic!("Muahahahah"));
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| target.force()));
+        let val: Thunk<_> = lazy!(redirect(target));
+        assert_eq!(val.try_force(), Err(LazyError::Poisoned));
+    }
+
+    #[test]
+    #[cfg(not(feature = "strict"))]
+    fn test_recursive_force_should_fail() {
+        let slot: Rc<Mutex<Option<Thunk<bool>>>> = Rc::new(Mutex::new(None));
+        let slot_clone = slot.clone();
+        let val: Thunk<_> = lazy!({
+            let this = slot_clone.lock().unwrap().clone().unwrap();
+            value(this.try_force() == Err(LazyError::Recursive))
+        });
+        *slot.lock().unwrap() = Some(val.clone());
+        assert_eq!(val.try_force(), Ok(&true));
+        *slot.lock().unwrap() = None;
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_long_chain_should_drop_on_small_stack() {
+        thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            let mut val: Thunk<u32> = strict(0);
+            for _ in 0..LONG_CHAIN {
+                let prev = val;
+                val = lazy!(redirect(prev));
+            }
+            drop(val);
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    // Dropping the chain afterwards needs the thread-local release queue.
+    #[cfg(feature = "std")]
+    fn test_long_redirect_chain_should_force_on_small_stack() {
+        thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            let mut val: Thunk<u32> = strict(7);
+            for _ in 0..LONG_CHAIN {
+                let prev = val;
+                val = lazy!(redirect(prev));
+            }
+            assert_eq!(*val, 7);
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_long_dependency_chain_should_force_on_small_stack() {
+        thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            let mut val: Thunk<u32> = strict(0);
+            for _ in 0..LONG_CHAIN {
+                let prev = val;
+                val = lazy_val!{ after prev;
+                    (*prev + 1) % 1000
+                };
+            }
+            assert_eq!(*val, 0);
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_dependencies_should_be_forced_in_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (order_x, order_y) = (order.clone(), order.clone());
+        let x: Thunk<u32> = lazy_val!{
+            order_x.lock().unwrap().push(1);
+            1
+        };
+        let y: Thunk<u32> = lazy_val!{
+            order_y.lock().unwrap().push(2);
+            2
+        };
+        let sum: Thunk<_> = lazy_val!{ after x, y;
+            *x + *y
+        };
+        assert_eq!(*sum, 3);
+        assert_eq!(*order.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "sync", not(feature = "strict")))]
+    fn test_thunk_should_be_poisoned_on_other_threads() {
+        let val: Arc<Thunk<u32, Atomic>> = Arc::new(lazy!(panic!("Muahahahah")));
+        let val_clone = val.clone();
+        assert!(thread::spawn(move || val_clone.force()).join().is_err());
+        assert_eq!(val.try_force(), Err(LazyError::Poisoned));
+    }
+
     struct Dropper(Arc<Mutex<u64>>);
 
     impl Drop for Dropper {
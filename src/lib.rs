@@ -1,14 +1,57 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-#[macro_use(debug_unreachable)]
-extern crate debug_unreachable;
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(any(test, feature = "testing"))]
+#[cfg_attr(test, macro_use)]
+extern crate quickcheck;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+
+/// `unreachable!()` in debug builds, and an optimization hint in release builds.
+macro_rules! debug_unreachable {
+    () => {
+        if cfg!(debug_assertions) {
+            unreachable!()
+        } else {
+            ::core::hint::unreachable_unchecked()
+        }
+    }
+}
 
 mod zero;
 #[macro_use]
 mod lazy;
+pub mod heap;
 mod measure;
 mod node;
 #[macro_use]
 mod digit;
+mod chunk;
 mod finger_tree;
 mod seq;
+mod stats;
+mod dot;
+mod invariants;
+#[cfg(any(feature = "quickcheck", feature = "proptest"))]
+mod arbitrary;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "rayon")]
+mod par;
 pub use seq::*;
+pub use stats::SeqStats;
+pub use invariants::InvariantError;
+#[cfg(feature = "quickcheck")]
+pub use arbitrary::ArbitrarySeq;
+#[cfg(feature = "rayon")]
+pub use par::*;
+pub use lazy::{Shared, Sharing, Local, LazyError};
+#[cfg(feature = "sync")]
+pub use lazy::Atomic;
@@ -1,43 +1,49 @@
-use std::ops;
+use core::ops;
+use core::fmt;
+use alloc::vec::Vec;
 
-use lazy::{Lazy, strict, value};
+use lazy::{Lazy, Sharing, strict, value};
 use self::Node::{Leaf,Node2,Node3};
 use measure::Measure;
-use digit::Digit;
+use stats::Walk;
+use dot::Dot;
+use invariants;
+use invariants::InvariantError;
+use digit::Split;
 use digit::Digit::{One,Two};
 
 /// A node in a 2-3 tree.
 ///
 /// The children are stared as lazy references
 #[derive(Debug)]
-pub enum Node<T, M>
+pub enum Node<T, M, K: Sharing>
 {
     Leaf(T),
-    Node2(M, Lazy<Node<T,M>>, Lazy<Node<T,M>>),
-    Node3(M, Lazy<Node<T,M>>, Lazy<Node<T,M>>, Lazy<Node<T,M>>),
+    Node2(M, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>),
+    Node3(M, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>, Lazy<Node<T,M,K>,K>),
 }
 
 /// Construct a lazy reference to a leaf
-pub fn leaf<T,M>(v: T) -> Lazy<Node<T,M>> {
+pub fn leaf<T,M,K: Sharing>(v: T) -> Lazy<Node<T,M,K>,K> {
     strict(Leaf(v))
 }
 
 /// Construct a lazy reference to a node with two children
-pub fn node2<T,M>(left: Lazy<Node<T,M>>, right: Lazy<Node<T,M>>) -> Lazy<Node<T,M>>
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static
+pub fn node2<T,M,K: Sharing>(left: Lazy<Node<T,M,K>,K>, right: Lazy<Node<T,M,K>,K>) -> Lazy<Node<T,M,K>,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy
 {
     lazy!{
         let m = left.measure() + right.measure();
-        let left: Lazy<Node<T,M>> = left;
+        let left: Lazy<Node<T,M,K>,K> = left;
         value(Node2(m, left, right))
     }
 }
 
 /// Construct a lazy reference to a node with three children
-pub fn node3<T,M>(left: Lazy<Node<T,M>>, middle: Lazy<Node<T,M>>, right: Lazy<Node<T,M>>) -> Lazy<Node<T,M>>
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static
+pub fn node3<T,M,K: Sharing>(left: Lazy<Node<T,M,K>,K>, middle: Lazy<Node<T,M,K>,K>, right: Lazy<Node<T,M,K>,K>) -> Lazy<Node<T,M,K>,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy
 {
     lazy!{
         let m = left.measure() + middle.measure() + right.measure();
@@ -56,9 +62,9 @@ macro_rules! node {
     }
 }
 
-pub fn lookup<T,M,P>(pred: P, i: M, node: &Node<T,M>) -> (&T,M)
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+pub fn lookup<T,M,K: Sharing,P>(pred: P, i: M, node: &Node<T,M,K>) -> (&T,M)
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool
 {
     match *node {
@@ -87,9 +93,9 @@ pub fn lookup<T,M,P>(pred: P, i: M, node: &Node<T,M>) -> (&T,M)
     }
 }
 
-pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, node: &Node<T,M>) -> Lazy<Node<T,M>>
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+pub fn adjust<T,M,K: Sharing,P,F>(func: F, pred: P, i: M, node: &Node<T,M,K>) -> Lazy<Node<T,M,K>,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool,
           F: FnOnce(&T) -> T
 {
@@ -119,10 +125,287 @@ pub fn adjust<T,M,P,F>(func: F, pred: P, i: M, node: &Node<T,M>) -> Lazy<Node<T,
     }
 }
 
-pub fn split_once<'a,T,M,P>(pred: &P, i: M, node: &'a Node<T,M>)
-                    -> (Option<Digit<T,M>>, &'a Lazy<Node<T,M>>, Option<Digit<T,M>>)
-    where T: Measure<M> + 'static,
-          M: ops::Add<Output=M> + Copy + 'static,
+/// Split the sorted `keys` into those that fall before the measure `j`, according to `pred`, and the rest.
+pub fn split_keys<'k,Q,M,P>(pred: &P, j: M, keys: &'k [Q]) -> (&'k [Q], &'k [Q])
+    where P: Fn(&Q, M) -> bool,
+          M: Copy
+{
+    keys.split_at(keys.partition_point(|key| pred(key, j)))
+}
+
+/// Look up all of the sorted `keys` in `node`, calling `found` once for each leaf that some of them fall in, with the measure before the leaf and those keys.
+///
+/// `pred(key, j)` says whether `key` falls before the measure `j`, as `pred` does for `lookup`. Each node on the paths to the keys is visited once, and the others are not searched.
+pub fn lookup_many<'a,T,M,K: Sharing,Q,P,F>(pred: &P, i: M, keys: &[Q], node: &'a Node<T,M,K>, found: &mut F)
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&'a T, M, &[Q])
+{
+    let mut i = i;
+    let mut keys = keys;
+    match *node {
+        Leaf(ref x) => found(x, i, keys),
+        Node2(_, ref left, ref right) => {
+            lookup_child(pred, &mut i, &mut keys, left, found);
+            lookup_child(pred, &mut i, &mut keys, right, found);
+        },
+        Node3(_, ref left, ref middle, ref right) => {
+            lookup_child(pred, &mut i, &mut keys, left, found);
+            lookup_child(pred, &mut i, &mut keys, middle, found);
+            lookup_child(pred, &mut i, &mut keys, right, found);
+        },
+    }
+}
+
+/// Look up the keys at the front of `keys` that fall in `node`, which comes after the measure `i`, and then move `i` and `keys` past it.
+pub fn lookup_child<'a,T,M,K: Sharing,Q,P,F>(pred: &P, i: &mut M, keys: &mut &[Q], node: &'a Lazy<Node<T,M,K>,K>, found: &mut F)
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&'a T, M, &[Q])
+{
+    if keys.is_empty() {
+        return
+    }
+    let j = *i + node.measure();
+    let (here, rest) = split_keys(pred, j, keys);
+    if !here.is_empty() {
+        lookup_many(pred, *i, here, node, found);
+    }
+    *i = j;
+    *keys = rest;
+}
+
+/// A copy of `node` with each leaf that some of the sorted `keys` fall in replaced by `func` of it, the measure before it, and those keys.
+///
+/// The nodes on the paths to the keys are copied once each, and the rest are shared with `node`.
+pub fn adjust_many<T,M,K: Sharing,Q,P,F>(func: &mut F, pred: &P, i: M, keys: &[Q], node: &Node<T,M,K>) -> Lazy<Node<T,M,K>,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&T, M, &[Q]) -> T
+{
+    let mut i = i;
+    let mut keys = keys;
+    match *node {
+        Leaf(ref x) => leaf(func(x, i, keys)),
+        Node2(_, ref left, ref right) => {
+            let left = adjust_child(func, pred, &mut i, &mut keys, left);
+            let right = adjust_child(func, pred, &mut i, &mut keys, right);
+            node2(left, right)
+        },
+        Node3(_, ref left, ref middle, ref right) => {
+            let left = adjust_child(func, pred, &mut i, &mut keys, left);
+            let middle = adjust_child(func, pred, &mut i, &mut keys, middle);
+            let right = adjust_child(func, pred, &mut i, &mut keys, right);
+            node3(left, middle, right)
+        },
+    }
+}
+
+/// Adjust the keys at the front of `keys` that fall in `node`, which comes after the measure `i`, and then move `i` and `keys` past it.
+pub fn adjust_child<T,M,K: Sharing,Q,P,F>(func: &mut F, pred: &P, i: &mut M, keys: &mut &[Q], node: &Lazy<Node<T,M,K>,K>) -> Lazy<Node<T,M,K>,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(&Q, M) -> bool,
+          F: FnMut(&T, M, &[Q]) -> T
+{
+    if keys.is_empty() {
+        return node.clone()
+    }
+    let j = *i + node.measure();
+    let (here, rest) = split_keys(pred, j, keys);
+    let result = if here.is_empty() {
+        node.clone()
+    } else {
+        adjust_many(func, pred, *i, here, node)
+    };
+    *i = j;
+    *keys = rest;
+    result
+}
+
+/// Apply `func` to every leaf, from left to right, giving a node with the same shape and measures.
+pub fn map<T,U,M,K: Sharing,F>(func: &mut F, node: &Node<T,M,K>) -> Node<U,M,K>
+    where M: Copy,
+          F: FnMut(&T) -> U
+{
+    match *node {
+        Leaf(ref x) => Leaf(func(x)),
+        Node2(m, ref left, ref right) => {
+            let left = strict(map(func, left));
+            let right = strict(map(func, right));
+            Node2(m, left, right)
+        },
+        Node3(m, ref left, ref middle, ref right) => {
+            let left = strict(map(func, left));
+            let middle = strict(map(func, middle));
+            let right = strict(map(func, right));
+            Node3(m, left, middle, right)
+        },
+    }
+}
+
+/// Apply `func` to every leaf, giving a node with the same shape and measures.
+///
+/// Nothing is evaluated until the result is forced, and then only this node is mapped; its children are mapped when they are forced.
+pub fn lazy_map<T,U,M,K: Sharing,F>(func: &Lazy<F,K>, node: &Lazy<Node<T,M,K>,K>) -> Lazy<Node<U,M,K>,K>
+    where T: 'static,
+          M: Copy,
+          F: Fn(&T) -> U + 'static
+{
+    let func = func.clone();
+    let node = node.clone();
+    lazy_val!{ after node;
+        match *node {
+            Leaf(ref x) => Leaf(func(x)),
+            Node2(m, ref left, ref right) =>
+                Node2(m, lazy_map(&func, left), lazy_map(&func, right)),
+            Node3(m, ref left, ref middle, ref right) =>
+                Node3(m, lazy_map(&func, left), lazy_map(&func, middle), lazy_map(&func, right)),
+        }
+    }
+}
+
+/// Reverse the order of the leaves, applying `leaf` to each, giving a node with the same measure, which must not depend on the order of the leaves.
+///
+/// As with `lazy_map`, nothing is evaluated until the result is forced, and then only this node is reversed.
+pub fn reverse<T,M,K: Sharing>(leaf: fn(&T) -> T, node: &Lazy<Node<T,M,K>,K>) -> Lazy<Node<T,M,K>,K>
+    where M: Copy
+{
+    let node = node.clone();
+    lazy_val!{ after node;
+        match *node {
+            Leaf(ref x) => Leaf(leaf(x)),
+            Node2(m, ref left, ref right) =>
+                Node2(m, reverse(leaf, right), reverse(leaf, left)),
+            Node3(m, ref left, ref middle, ref right) =>
+                Node3(m, reverse(leaf, right), reverse(leaf, middle), reverse(leaf, left)),
+        }
+    }
+}
+
+/// Add `node`, and the evaluated nodes below it, to `walk`, without forcing anything.
+pub fn stats<T,M,K: Sharing>(node: &Lazy<Node<T,M,K>,K>, walk: &mut Walk) {
+    let node = match walk.thunk(node) {
+        Some(node) => node,
+        None => return,
+    };
+    match *node {
+        Leaf(_) =>
+            walk.stats.leaves += 1,
+        Node2(_, ref left, ref right) => {
+            walk.stats.node2 += 1;
+            stats(left, walk);
+            stats(right, walk);
+        },
+        Node3(_, ref left, ref middle, ref right) => {
+            walk.stats.node3 += 1;
+            stats(left, walk);
+            stats(middle, walk);
+            stats(right, walk);
+        },
+    }
+}
+
+/// Draw `node`, and the evaluated nodes below it, with an edge from `from`, without forcing anything.
+pub fn dot<T,M,K: Sharing>(from: &str, node: &Lazy<Node<T,M,K>,K>, graph: &mut Dot)
+    where T: Measure<M>,
+          M: fmt::Display + Copy
+{
+    let (id, node) = match graph.thunk(from, node) {
+        Some(node) => node,
+        None => return,
+    };
+    match *node {
+        Leaf(ref x) =>
+            graph.node(&id, format!("Leaf\\n{}", x.measure()), false),
+        Node2(m, ref left, ref right) => {
+            graph.node(&id, format!("Node2\\n{}", m), false);
+            dot(&id, left, graph);
+            dot(&id, right, graph);
+        },
+        Node3(m, ref left, ref middle, ref right) => {
+            graph.node(&id, format!("Node3\\n{}", m), false);
+            dot(&id, left, graph);
+            dot(&id, middle, graph);
+            dot(&id, right, graph);
+        },
+    }
+}
+
+/// Check the measures cached in `node`, and the nodes below it, forcing them, and give the measure of `node`.
+///
+/// The leaves should all be `height` levels below `node`, which is in a digit at `level` of the spine.
+pub fn check<T,M,K: Sharing>(node: &Lazy<Node<T,M,K>,K>, height: usize, level: usize) -> Result<M, InvariantError>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy + PartialEq
+{
+    let (cached, measure) = match *invariants::force(node)? {
+        Leaf(ref x) if height == 0 =>
+            return Ok(x.measure()),
+        Node2(m, ref left, ref right) if height > 0 => {
+            let measure = check(left, height - 1, level)? + check(right, height - 1, level)?;
+            (m, measure)
+        },
+        Node3(m, ref left, ref middle, ref right) if height > 0 => {
+            let measure = check(left, height - 1, level)? +
+                check(middle, height - 1, level)? +
+                check(right, height - 1, level)?;
+            (m, measure)
+        },
+        _ => return Err(InvariantError::Depth { level }),
+    };
+    if cached == measure {
+        Ok(measure)
+    } else {
+        Err(InvariantError::Measure { level })
+    }
+}
+
+pub fn lookup_mut<T,M,K: Sharing,P>(pred: P, i: M, node: &mut Lazy<Node<T,M,K>,K>) -> (&mut T,M)
+    where T: Measure<M> + Clone,
+          M: ops::Add<Output=M> + Copy,
+          P: Fn(M) -> bool
+{
+    match *node.make_mut() {
+        Leaf(ref mut x) => (x, i),
+        Node2(_, ref mut left, ref mut right) => {
+            let i1 = i + left.measure();
+            if pred(i1) {
+                lookup_mut(pred, i, left)
+            } else {
+                lookup_mut(pred, i1, right)
+            }
+        },
+        Node3(_, ref mut left, ref mut middle, ref mut right) => {
+            let i1 = i + left.measure();
+            if pred(i1) {
+                lookup_mut(pred, i, left)
+            } else {
+                let i2 = i1 + middle.measure();
+                if pred(i2) {
+                    lookup_mut(pred, i1, middle)
+                } else {
+                    lookup_mut(pred, i2, right)
+                }
+            }
+        }
+    }
+}
+
+/// The value in a leaf.
+pub fn leaf_value<T,M,K: Sharing>(node: &Node<T,M,K>) -> &T {
+    match *node {
+        Leaf(ref x) => x,
+        _ => panic!("leaf_value on internal node"),
+    }
+}
+
+pub fn split_once<'a,T,M,K: Sharing,P>(pred: &P, i: M, node: &'a Node<T,M,K>) -> Split<'a,T,M,K>
+    where T: Measure<M>,
+          M: ops::Add<Output=M> + Copy,
           P: Fn(M) -> bool
 {
     match *node {
@@ -150,45 +433,58 @@ pub fn split_once<'a,T,M,P>(pred: &P, i: M, node: &'a Node<T,M>)
     }
 }
 
-impl<T,M> Node<T,M>
+impl<T,M,K: Sharing> Clone for Node<T,M,K>
+    where T: Clone,
+          M: Copy
+{
+    fn clone(&self) -> Node<T,M,K> {
+        match *self {
+            Leaf(ref x) => Leaf(x.clone()),
+            Node2(m, ref x0, ref x1) => Node2(m, x0.clone(), x1.clone()),
+            Node3(m, ref x0, ref x1, ref x2) => Node3(m, x0.clone(), x1.clone(), x2.clone()),
+        }
+    }
+}
+
+impl<T,M,K: Sharing> Node<T,M,K>
 {
     /// Iterates over the values in the leaves
-    pub fn iter<'a>(&'a self) -> Iter<'a,T,M> {
+    pub fn iter<'a>(&'a self) -> Iter<'a,T,M,K> {
         Iter::new(self)
     }
 }
 
-impl<'a,T,M> IntoIterator for &'a Node<T,M> {
+impl<'a,T,M,K: Sharing> IntoIterator for &'a Node<T,M,K> {
     type Item = &'a T;
 
-    type IntoIter = Iter<'a, T, M>;
+    type IntoIter = Iter<'a, T, M, K>;
 
-    fn into_iter(self) -> Iter<'a,T,M> {
+    fn into_iter(self) -> Iter<'a,T,M,K> {
         self.iter()
     }
 }
 
-impl<T,M> Measure<M> for Node<T,M>
+impl<T,M,K: Sharing> Measure<M> for Node<T,M,K>
     where T: Measure<M>,
           M: Copy
 {
     fn measure(&self) -> M {
-        match self {
-            &Leaf(ref value) => value.measure(),
-            &Node2(measure, _, _) => measure,
-            &Node3(measure, _, _, _) => measure,
+        match *self {
+            Leaf(ref value) => value.measure(),
+            Node2(measure, _, _) => measure,
+            Node3(measure, _, _, _) => measure,
         }
     }
 }
 
 /// Iterator over the values in the leaves of a 2-3 tree
 #[derive(Debug)]
-pub struct Iter<'a, T:'a, M:'a> {
-    stack: Vec<&'a Node<T,M>>,
+pub struct Iter<'a, T:'a, M:'a, K: Sharing> {
+    stack: Vec<&'a Node<T,M,K>>,
 }
 
-impl<'a, T, M> Iter<'a, T, M> {
-    fn new(node: &'a Node<T,M>) -> Iter<'a, T, M> {
+impl<'a, T, M, K: Sharing> Iter<'a, T, M, K> {
+    fn new(node: &'a Node<T,M,K>) -> Iter<'a, T, M, K> {
         Iter {
             stack: vec![node],
         }
@@ -196,33 +492,28 @@ impl<'a, T, M> Iter<'a, T, M> {
     /// An `iter` that is empty (yields no values).
     ///
     /// This is helpful in  `finger_tree::Iter`.
-    pub fn empty() -> Iter<'a, T, M> {
+    pub fn empty() -> Iter<'a, T, M, K> {
         Iter {
             stack: vec![],
         }
     }
 }
 
-impl<'a, T:'a, M> Iterator for Iter<'a,T,M> {
+impl<'a, T:'a, M, K: Sharing> Iterator for Iter<'a,T,M,K> {
     type Item = &'a T;
     fn next(&mut self) -> Option<&'a T> {
-        let mut node: &'a Node<T,M> = {
-            match self.stack.pop() {
-                None => return None,
-                Some(node) => node
-            }
-        };
+        let mut node: &'a Node<T,M,K> = self.stack.pop()?;
         loop {
             match *node {
                 Leaf(ref x) => return Some(x),
                 Node2(_, ref left, ref right) => {
-                    self.stack.push(&*right);
-                    node = &*left;
+                    self.stack.push(right);
+                    node = left;
                 },
                 Node3(_, ref left, ref middle, ref right) => {
-                    self.stack.push(&*right);
-                    self.stack.push(&*middle);
-                    node = &*left;
+                    self.stack.push(right);
+                    self.stack.push(middle);
+                    node = left;
                 }
             }
         }
@@ -233,6 +524,7 @@ impl<'a, T:'a, M> Iterator for Iter<'a,T,M> {
 mod test {
     use super::*;
     use measure::Measure;
+    use lazy::Local;
 
     struct Item<T>(T);
 
@@ -244,7 +536,7 @@ mod test {
 
     #[test]
     fn test_node_iter() {
-        let tree: Lazy<Node<Item<u32>, usize>> =
+        let tree: Lazy<Node<Item<u32>, usize, Local>> =
             node2(
                 node3(
                     leaf(Item(0)),
@@ -260,7 +552,7 @@ mod test {
 
     #[test]
     fn test_tree23_measure() {
-        let tree: Lazy<Node<Item<u32>, usize>> =
+        let tree: Lazy<Node<Item<u32>, usize, Local>> =
             node2(
                 node3(
                     leaf(Item(0)),
@@ -0,0 +1,121 @@
+use std::fmt;
+
+use rayon::iter::{ParallelIterator, IndexedParallelIterator, IntoParallelIterator, FromParallelIterator};
+use rayon::iter::plumbing::{bridge, Consumer, ProducerCallback, UnindexedConsumer, Producer};
+
+use lazy::Atomic;
+use seq::{Seq, SyncSeq, Iter};
+
+/// A parallel iterator over the elements of a `SyncSeq`.
+///
+/// The work is divided by splitting the underlying finger tree at its digits, middle trees and nodes, so no elements are copied or re-indexed.
+pub struct ParIter<'a, T: 'a> {
+    seq: &'a SyncSeq<T>,
+}
+
+impl<'a, T: Send + Sync> fmt::Debug for ParIter<'a, T>
+    where T: fmt::Debug
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ParIter").field("seq", self.seq).finish()
+    }
+}
+
+impl<T: Send + Sync> Seq<T, Atomic> {
+    /// A parallel iterator over the sequence. Time: *O(1)*
+    pub fn par_iter(&self) -> ParIter<'_, T> {
+        ParIter {
+            seq: self,
+        }
+    }
+
+    /// A new sequence with `func` applied to every element, computed in parallel. Time: *O(n)*
+    pub fn par_map<U, F>(&self, func: F) -> SyncSeq<U>
+        where U: Send + Sync,
+              F: Fn(&T) -> U + Sync + Send
+    {
+        self.par_iter().map(func).collect()
+    }
+
+    /// Fold the elements in parallel. Time: *O(n)*
+    ///
+    /// The sequence is divided into pieces, each of which is folded with `fold` starting from `identity()`, and the results of adjacent pieces are combined with `reduce`, preserving their order.
+    pub fn par_fold<A, ID, F, R>(&self, identity: ID, fold: F, reduce: R) -> A
+        where A: Send,
+              ID: Fn() -> A + Sync + Send,
+              F: Fn(A, &T) -> A + Sync + Send,
+              R: Fn(A, A) -> A + Sync + Send
+    {
+        self.par_iter().fold(&identity, fold).reduce(&identity, reduce)
+    }
+}
+
+impl<'a, T: Send + Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: UnindexedConsumer<&'a T>
+    {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.seq.len())
+    }
+}
+
+impl<'a, T: Send + Sync> IndexedParallelIterator for ParIter<'a, T> {
+    fn len(&self) -> usize {
+        self.seq.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result
+        where C: Consumer<&'a T>
+    {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+        where CB: ProducerCallback<&'a T>
+    {
+        callback.callback(IterProducer(self.seq.iter()))
+    }
+}
+
+struct IterProducer<'a, T: 'a>(Iter<'a, T, Atomic>);
+
+impl<'a, T: Send + Sync> Producer for IterProducer<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, Atomic>;
+
+    fn into_iter(self) -> Iter<'a, T, Atomic> {
+        self.0
+    }
+
+    fn split_at(self, index: usize) -> (IterProducer<'a, T>, IterProducer<'a, T>) {
+        let (before, after) = self.0.split_at(index);
+        (IterProducer(before), IterProducer(after))
+    }
+}
+
+impl<'a, T: Send + Sync> IntoParallelIterator for &'a SyncSeq<T> {
+    type Item = &'a T;
+    type Iter = ParIter<'a, T>;
+
+    fn into_par_iter(self) -> ParIter<'a, T> {
+        self.par_iter()
+    }
+}
+
+impl<T: Send + Sync> FromParallelIterator<T> for SyncSeq<T> {
+    fn from_par_iter<I>(par_iter: I) -> SyncSeq<T>
+        where I: IntoParallelIterator<Item=T>
+    {
+        par_iter.into_par_iter()
+            .fold(Seq::empty_sync, |mut seq, x| {
+                seq.push_back_mut(x);
+                seq
+            })
+            .reduce(Seq::empty_sync, |before, after| before.append(&after))
+    }
+}
@@ -1,55 +1,171 @@
 
-use std::iter;
-use std::ops;
-use std::convert;
-use std::cmp;
-use std::fmt;
+use core::iter;
+use core::ops;
+use core::convert;
+use core::cmp;
+use core::fmt;
+use core::slice;
+use core::mem;
+use core::hash::{Hash, Hasher};
+use core::ops::{RangeBounds, Bound};
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
 
-use lazy::Lazy;
+use lazy::{Lazy, Sharing, Local, Shared, strict};
+#[cfg(feature = "sync")]
+use lazy::Atomic;
 
+use chunk::{Chunk, CHUNK_SIZE};
 use finger_tree;
 use finger_tree::FingerTree;
+use finger_tree::FingerTree::{Empty, Single, Deep};
 use node;
+use node::Node;
+use node::Node::{Leaf, Node2, Node3};
 use measure::Measure;
-
-#[derive(Debug)]
-struct Item<T>(T);
-
-impl<T> Measure<usize> for Item<T> {
-    fn measure(&self) -> usize {1}
-}
+use stats::{SeqStats, Walk};
+use dot::Dot;
+use invariants::InvariantError;
 
 /// A data-structure implementing an immutable sequence of values.
 ///
 /// An amortized running time is given for each operation, with *n* referring to the length of the sequence and *i* being the integral index used by some operations. These bounds hold even in a persistent (shared) setting.
 ///
+/// The elements are stored in short contiguous chunks at the leaves of the tree, which can be accessed directly using `slices`.
+///
+/// With the `strict` feature, every node is built eagerly, rather than deferring work to whichever later operation first reads it. The amortized bounds then only hold if each version of the sequence is used once; repeatedly reusing an old version can cost up to *O(log(n))* per operation.
+///
+/// Persistent operations never change the sequences they are given, even if a function they call panics. A part of a sequence whose deferred evaluation panicked (for example, a chunk of a `lazy_map` whose function panicked) is poisoned: reading it again panics rather than re-running the computation. The in-place operations (`push_back_mut` and friends) leave the sequence valid if they are interrupted by a panic, but elements that could not be evaluated are dropped from it.
+///
+/// The elements may borrow data, such as strings in an arena, and the sequence can not outlive it:
+///
+/// ```
+/// # use immutable_seq::Seq;
+/// let text = String::from("the quick brown fox");
+/// let words: Seq<&str> = text.split(' ').collect();
+/// assert_eq!(words.push_back("jumps").get(4), Some(&"jumps"));
+/// ```
+///
+/// ```compile_fail
+/// # use immutable_seq::Seq;
+/// let words: Seq<&str> = {
+///     let text = String::from("the quick brown fox");
+///     text.split(' ').collect()
+/// };
+/// ```
+///
+/// A `Seq` is invariant in its element type, since sequences that share nodes may also share space for new elements, so a `Seq<&'static str>` can not be used where a `Seq<&'a str>` is expected.
+///
+/// The nodes of a `Seq<T>`, which is a `Seq<T, Local>`, are reference counted like an `Rc`, so it stays on one thread, and `T` can be any type. With the `sync` feature, a `SyncSeq<T>`, which is a `Seq<T, Atomic>`, counts its nodes like an `Arc`, and is `Send + Sync` when `T` is. The two have the same methods, apart from their constructors, such as `Seq::empty` and `SyncSeq::empty_sync`, and their conversions from other collections, which are only implemented for `Seq`.
+///
 /// This implementation is based on Haskell's Data.Sequence library (http://hackage.haskell.org/package/containers/docs/Data-Sequence.html), and the following paper:
 /// * Ralf Hinze and Ross Paterson, "Finger trees: a simple general-purpose data structure", Journal of Functional Programming 16:2 (2006) pp 197-217. http://staff.city.ac.uk/~ross/papers/FingerTree.html
-pub struct Seq<T> (Lazy<FingerTree<Item<T>,usize>>);
+pub struct Seq<T, K: Sharing = Local> (Lazy<FingerTree<Chunk<T,K>,usize,K>,K>);
+
+/// A `Seq` that can be shared between threads.
+///
+/// ```
+/// # use immutable_seq::SyncSeq;
+/// use std::thread;
+///
+/// let seq: SyncSeq<u32> = (0..100).collect();
+/// let sum = thread::spawn(move || seq.iter().sum::<u32>()).join().unwrap();
+/// assert_eq!(sum, 4950);
+/// ```
+#[cfg(feature = "sync")]
+pub type SyncSeq<T> = Seq<T, Atomic>;
+
+/// Add `chunk` to the front of `tree`, unless it is empty.
+fn cons_chunk<T,K: Sharing>(chunk: Chunk<T,K>, tree: Lazy<FingerTree<Chunk<T,K>,usize,K>,K>) -> Lazy<FingerTree<Chunk<T,K>,usize,K>,K>
+    where T: Shared<K>
+{
+    if chunk.is_empty() {
+        tree
+    } else {
+        finger_tree::cons_node(node::leaf(chunk), tree)
+    }
+}
+
+/// Add `chunk` to the back of `tree`, unless it is empty.
+fn snoc_chunk<T,K: Sharing>(tree: Lazy<FingerTree<Chunk<T,K>,usize,K>,K>, chunk: Chunk<T,K>) -> Lazy<FingerTree<Chunk<T,K>,usize,K>,K>
+    where T: Shared<K>
+{
+    if chunk.is_empty() {
+        tree
+    } else {
+        finger_tree::snoc_node(tree, node::leaf(chunk))
+    }
+}
 
-impl<T:'static> Seq<T> {
+impl<T: Shared> Seq<T> {
     /// The empty sequence. Time: *O(1)*
     pub fn empty() -> Seq<T> {
-        Seq(finger_tree::empty())
+        Seq::nil()
     }
 
     /// A sequence with a single value. Time *O(1)*
     pub fn singleton(x: T) -> Seq<T> {
-        Seq(finger_tree::single(node::leaf(Item(x))))
+        Seq::unit(x)
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Shared<Atomic>> Seq<T, Atomic> {
+    /// The empty sequence, which can be shared between threads. Time: *O(1)*
+    pub fn empty_sync() -> SyncSeq<T> {
+        Seq::nil()
+    }
+
+    /// A sequence with a single value, which can be shared between threads. Time *O(1)*
+    pub fn singleton_sync(x: T) -> SyncSeq<T> {
+        Seq::unit(x)
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> Seq<T,K> {
+    /// The empty sequence, with either kind of sharing.
+    fn nil() -> Seq<T,K> {
+        Seq(finger_tree::empty())
+    }
+
+    /// A sequence with a single value, with either kind of sharing.
+    fn unit(x: T) -> Seq<T,K> {
+        Seq(finger_tree::single(node::leaf(Chunk::single_back(x))))
     }
 
     /// A new sequence that is `self` with `x` added to the front. Time: *O(1)*
-    pub fn push_front(&self, x: T) -> Seq<T> {
-        Seq(finger_tree::cons_node(node::leaf(Item(x)), self.inner().clone()))
+    pub fn push_front(&self, x: T) -> Seq<T,K> {
+        let x = match finger_tree::front(self.inner()) {
+            None => return Seq::unit(x),
+            Some(chunk) => match chunk.push_front(x) {
+                Ok(chunk) =>
+                    return Seq(finger_tree::replace_front(self.inner(), node::leaf(chunk))),
+                Err(x) => x,
+            }
+        };
+        Seq(finger_tree::cons_node(node::leaf(Chunk::single_front(x)), self.inner().clone()))
     }
 
     /// A new sequence that is `self` with `x` added to the back. Time: *O(1)*
-    pub fn push_back(&self, x: T) -> Seq<T> {
-        Seq(finger_tree::snoc_node(self.inner().clone(), node::leaf(Item(x))))
+    pub fn push_back(&self, x: T) -> Seq<T,K> {
+        let x = match finger_tree::back(self.inner()) {
+            None => return Seq::unit(x),
+            Some(chunk) => match chunk.push_back(x) {
+                Ok(chunk) =>
+                    return Seq(finger_tree::replace_back(self.inner(), node::leaf(chunk))),
+                Err(x) => x,
+            }
+        };
+        Seq(finger_tree::snoc_node(self.inner().clone(), node::leaf(Chunk::single_back(x))))
     }
 
     /// The concatenation of `self` with `other`. Time: *O(log(min(n1,n2)))*
-    pub fn append(&self, other: &Seq<T>) -> Seq<T> {
+    pub fn append(&self, other: &Seq<T,K>) -> Seq<T,K> {
         Seq(finger_tree::tree_tree(self.inner().clone(), other.inner().clone()))
     }
 
@@ -65,47 +181,66 @@ impl<T:'static> Seq<T> {
 
     /// The first element in the sequence, if it exists. Time: *O(1)*
     pub fn front(&self) -> Option<&T> {
-        finger_tree::front(self.inner()).map(|&Item(ref x)| x)
+        finger_tree::front(self.inner()).and_then(|chunk| chunk.first())
     }
 
     /// The back element, if it exsts. Time: *O(1)*
     pub fn back(&self) -> Option<&T> {
-        finger_tree::back(self.inner()).map(|&Item(ref x)| x)
+        finger_tree::back(self.inner()).and_then(|chunk| chunk.last())
     }
 
     /// A new sequence that is `self` with the front element removed, together with the front element (if it exists). Time: *O(1)*
-    pub fn pop_front(&self) -> Seq<T> {
-        Seq(finger_tree::pop_front(self.inner()))
+    pub fn pop_front(&self) -> Seq<T,K> {
+        match finger_tree::front(self.inner()) {
+            Some(chunk) if chunk.len() > 1 =>
+                Seq(finger_tree::replace_front(self.inner(), node::leaf(chunk.slice(1, chunk.len())))),
+            _ => Seq(finger_tree::pop_front(self.inner())),
+        }
     }
 
     /// A new sequence that is `self` with the back element removed, together with the back element (if it exists). Time: *O(1)*
-    pub fn pop_back(&self) -> Seq<T> {
-        Seq(finger_tree::pop_back(self.inner()))
+    pub fn pop_back(&self) -> Seq<T,K> {
+        match finger_tree::back(self.inner()) {
+            Some(chunk) if chunk.len() > 1 =>
+                Seq(finger_tree::replace_back(self.inner(), node::leaf(chunk.slice(0, chunk.len() - 1)))),
+            _ => Seq(finger_tree::pop_back(self.inner())),
+        }
     }
 
     /// A new sequence with the element at index `i` replaced by `f(self[i])`. Time: *O(log(min(i,n-i)))*
     ///
     /// If `i` is out of range, returns a clone of `self`.
-    pub fn adjust<F>(&self, i: usize, func: F) -> Seq<T>
+    pub fn adjust<F>(&self, i: usize, func: F) -> Seq<T,K>
         where F: FnOnce(&T) -> T
     {
         if i >= self.len() {
             return self.clone()
         }
-        Seq(finger_tree::adjust(move |&Item(ref x)| Item(func(x)), move |j| {i < j}, 0, self.inner()))
+        if finger_tree::lookup(move |j| {i < j}, 0, self.inner()).0.len() == 1 {
+            let func = move |chunk: &Chunk<T,K>| Chunk::single(func(&chunk[0]));
+            return Seq(finger_tree::adjust(func, move |j| {i < j}, 0, self.inner()))
+        }
+        // Replace the chunk containing `i` with the elements before `i`, the new element, and the elements after `i`.
+        let (before,x,after) = finger_tree::split(&move |j| {i < j}, 0, self.inner());
+        let chunk = node::leaf_value(x);
+        let k = i - before.measure();
+        let before = snoc_chunk(before, chunk.slice(0, k));
+        let before = finger_tree::snoc_node(before, node::leaf(Chunk::single(func(&chunk[k]))));
+        let after = cons_chunk(chunk.slice(k + 1, chunk.len()), after);
+        Seq(finger_tree::tree_tree(before, after))
     }
 
     /// A new sequence with the element at index `i` replaced by `x`. Time: *O(log(min(i,n-i)))*
     ///
     /// If `i` is out of range, returns a clone of `self`.
-    pub fn update(&self, i: usize, x: T) -> Seq<T> {
+    pub fn update(&self, i: usize, x: T) -> Seq<T,K> {
         self.adjust(i, move |_| x)
     }
 
     /// A new sequence consisting of only the first `count` elements. Time: *O(log(min(count, n - count)))*
     ///
     /// If `count >= self.len()`, then returns a clone of `self`.
-    pub fn truncate(&self, count: usize) -> Seq<T> {
+    pub fn truncate(&self, count: usize) -> Seq<T,K> {
         let (before,_) = self.split(count);
         before
     }
@@ -113,7 +248,7 @@ impl<T:'static> Seq<T> {
     /// A new sequence consisting of only the last `count` elements. Time: *O(log(min(count,n - count)))*
     ///
     /// If `count >= self.len()`, then returns a clone of `self`.
-    pub fn skip(&self, count: usize) -> Seq<T> {
+    pub fn skip(&self, count: usize) -> Seq<T,K> {
         let (_,after) = self.split(count);
         after
     }
@@ -121,22 +256,103 @@ impl<T:'static> Seq<T> {
     /// Two new sequences, consisting of the first `count` elements, and the remaining elements, respectively. Time: *O(log(min(count,n-count)))*
     ///
     /// If `count >= self.len()`, then the first sequence is a clone of `self` and the second is empty.
-    pub fn split(&self, n: usize) -> (Seq<T>, Seq<T>) {
+    pub fn split(&self, n: usize) -> (Seq<T,K>, Seq<T,K>) {
         if n >= self.len() {
-            return (self.clone(), Seq::empty())
+            return (self.clone(), Seq::nil())
         }
         let (before,x,after) = finger_tree::split(&move |i| {n < i}, 0, self.inner());
-        (Seq(before), Seq(finger_tree::cons_node(x.clone(), after)))
+        let chunk = node::leaf_value(x);
+        let k = n - before.measure();
+        (Seq(snoc_chunk(before, chunk.slice(0, k))),
+         Seq(cons_chunk(chunk.slice(k, chunk.len()), after)))
+    }
+
+    /// The pieces of the sequence between each of the sorted `indices`, so that there is one more piece than there are indices. Time: *O(k log(n/k))* for *k* indices
+    ///
+    /// Indices after the end of the sequence are treated as its length, as by `split`, giving empty pieces at the end. The pieces share structure with `self`.
+    ///
+    /// The bound comes from splitting recursively: the sequence is split at the middle index, and then each half at the indices in it, so the splits at each level of the recursion are done on parts whose lengths add up to at most *n*. Splitting the whole sequence at each index in turn would take *O(k log(n))*.
+    ///
+    /// Panics if `indices` is not sorted.
+    pub fn split_at_many(&self, indices: &[usize]) -> Vec<Seq<T,K>> {
+        assert!(indices.windows(2).all(|w| w[0] <= w[1]), "split indices must be sorted");
+        let mut pieces = Vec::with_capacity(indices.len() + 1);
+        self.split_into(0, indices, &mut pieces);
+        pieces
+    }
+
+    /// Add the pieces of `self` between each of the sorted `indices` to `pieces`, where `self` starts at index `offset`.
+    fn split_into(&self, offset: usize, indices: &[usize], pieces: &mut Vec<Seq<T,K>>) {
+        if indices.is_empty() {
+            pieces.push(self.clone());
+            return
+        }
+        let mid = indices.len() / 2;
+        let (before, after) = self.split(indices[mid] - offset);
+        before.split_into(offset, &indices[..mid], pieces);
+        after.split_into(indices[mid], &indices[mid + 1..], pieces);
+    }
+
+    /// The sequence split into `k` contiguous parts, whose lengths differ by at most one, with the longer parts first. Time: *O(k log(n/k))*
+    ///
+    /// If `k > self.len()`, then the last parts are empty.
+    ///
+    /// Panics if `k` is zero.
+    pub fn partition_into(&self, k: usize) -> Vec<Seq<T,K>> {
+        assert!(k > 0, "cannot partition a sequence into zero parts");
+        let (size, longer) = (self.len() / k, self.len() % k);
+        let indices: Vec<usize> = (1..k).map(|j| j * size + cmp::min(j, longer)).collect();
+        self.split_at_many(&indices)
+    }
+
+    /// An iterator over the contiguous parts of the sequence of `size` elements, with a shorter part at the end if `size` does not divide the length. Time: *O(1)*
+    ///
+    /// Each part is a sequence that shares structure with `self`, and is split from it in *O(log(size))* time.
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunks(&self, size: usize) -> Chunks<T,K> {
+        assert!(size != 0, "chunk size must be non-zero");
+        Chunks {
+            rest: self.clone(),
+            size,
+        }
+    }
+
+    /// An iterator over the overlapping runs of `size` consecutive elements of the sequence, each starting one element after the last. Time: *O(1)*
+    ///
+    /// Each run is a sequence that shares structure with `self`, and is split from it in *O(log(n))* time. There are no runs if `size > self.len()`.
+    ///
+    /// Panics if `size` is zero.
+    pub fn windows(&self, size: usize) -> Windows<T,K> {
+        assert!(size != 0, "window size must be non-zero");
+        Windows {
+            seq: self.clone(),
+            size,
+            start: 0,
+            end: (self.len() + 1).saturating_sub(size),
+        }
+    }
+
+    /// A new sequence consisting of the elements in `range`. Time: *O(log(n))*
+    ///
+    /// Panics if the start of the range is after its end, or the end is after the end of the sequence, like indexing a slice.
+    pub fn subseq<R: RangeBounds<usize>>(&self, range: R) -> Seq<T,K> {
+        let (_,middle,_) = self.split_range(range);
+        middle
     }
 
     /// A new sequence with the element at index `i` removed, together with the element at index `i`, if it exists. Time: *O(log(min(i,n-i)))*
     ///
     /// If `i` is out of range, then the returned sequence is a clone of `self`, and the element is `None`.
-    pub fn remove(&self, i: usize) -> Seq<T> {
+    pub fn remove(&self, i: usize) -> Seq<T,K> {
         if i >= self.len() {
             return self.clone()
         }
-        let (before,_,after) = finger_tree::split(&move |j| {i < j}, 0, self.inner());
+        let (before,x,after) = finger_tree::split(&move |j| {i < j}, 0, self.inner());
+        let chunk = node::leaf_value(x);
+        let k = i - before.measure();
+        let before = snoc_chunk(before, chunk.slice(0, k));
+        let after = cons_chunk(chunk.slice(k + 1, chunk.len()), after);
         Seq(finger_tree::tree_tree(before, after))
     }
 
@@ -145,14 +361,61 @@ impl<T:'static> Seq<T> {
     /// If `i < self.len()`, then `x` will immediately precede `self[i]` in the new sequence.
     ///
     /// if `i >= self.len()`, then `x` will be the last element in the new sequence.
-    pub fn insert(&self, i: usize, x: T) -> Seq<T> {
+    pub fn insert(&self, i: usize, x: T) -> Seq<T,K> {
         if i >= self.len() {
             return self.push_back(x)
         }
-        let (before,y,after) = finger_tree::split(&move |j| {i < j}, 0, self.inner());
-        let before = finger_tree::snoc_node(before, node::leaf(Item(x)));
-        let after = finger_tree::cons_node(y.clone(), after);
-        Seq(finger_tree::tree_tree(before, after))
+        let (before,after) = self.split(i);
+        before.push_back(x).append(&after)
+    }
+
+    /// A new sequence with the elements of `other` inserted at index `i`. Time: *O(log(min(i,n-i)) + log(min(n,m)))*, where *m* is the length of `other`.
+    ///
+    /// If `i >= self.len()`, then `other` is appended to `self`.
+    pub fn insert_seq(&self, i: usize, other: &Seq<T,K>) -> Seq<T,K> {
+        let (before,after) = self.split(i);
+        before.append(other).append(&after)
+    }
+
+    /// A new sequence with the elements in `range` replaced by the elements of `other`. Time: *O(log(n) + log(m))*, where *m* is the length of `other`.
+    ///
+    /// Panics if the start of the range is after its end, or the end is after the end of the sequence, like `subseq`.
+    pub fn replace_range<R: RangeBounds<usize>>(&self, range: R, other: &Seq<T,K>) -> Seq<T,K> {
+        let (before,_,after) = self.split_range(range);
+        before.append(other).append(&after)
+    }
+
+    /// A new sequence with the elements in `range` removed, together with the removed elements. Time: *O(log(n))*
+    ///
+    /// Panics if the start of the range is after its end, or the end is after the end of the sequence, like `subseq`.
+    pub fn remove_range<R: RangeBounds<usize>>(&self, range: R) -> (Seq<T,K>, Seq<T,K>) {
+        let (before,removed,after) = self.split_range(range);
+        (before.append(&after), removed)
+    }
+
+    /// A new sequence with the first `k` elements moved to the back. Time: *O(log(min(k,n-k)))*
+    ///
+    /// Panics if `k > self.len()`, like `VecDeque::rotate_left`.
+    pub fn rotate_left(&self, k: usize) -> Seq<T,K> {
+        assert!(k <= self.len(), "rotate by {} is out of range for sequence of length {}", k, self.len());
+        let (before,after) = self.split(k);
+        after.append(&before)
+    }
+
+    /// A new sequence with the last `k` elements moved to the front. Time: *O(log(min(k,n-k)))*
+    ///
+    /// Panics if `k > self.len()`, like `VecDeque::rotate_right`.
+    pub fn rotate_right(&self, k: usize) -> Seq<T,K> {
+        assert!(k <= self.len(), "rotate by {} is out of range for sequence of length {}", k, self.len());
+        self.rotate_left(self.len() - k)
+    }
+
+    /// The elements before `range`, in `range`, and after it.
+    fn split_range<R: RangeBounds<usize>>(&self, range: R) -> (Seq<T,K>, Seq<T,K>, Seq<T,K>) {
+        let (start, end) = bounds(range, self.len());
+        let (before,rest) = self.split(start);
+        let (middle,after) = rest.split(end - start);
+        (before, middle, after)
     }
 
     /// Get the element at index `i`, if it exists. Time: *O(log(min(i,n-i)))*
@@ -160,23 +423,694 @@ impl<T:'static> Seq<T> {
         if i >= self.len() {
             return None
         }
-        match finger_tree::lookup(move |j| {i < j}, 0, self.inner()) {
-            (&Item(ref x), _) => Some(x)
+        let (chunk, j) = finger_tree::lookup(move |j| {i < j}, 0, self.inner());
+        chunk.get(i - j)
+    }
+
+    /// References to the elements at each of `indices`, in the same order, with `None` for the indices that are out of range. Time: *O(k log(n/k))* for *k* sorted indices
+    ///
+    /// The elements are found in a single walk down the tree, which visits each node on the paths to them once, so this is faster than calling `get` for each index. Indices that are not sorted are sorted first, in *O(k log(k))* time.
+    pub fn get_many(&self, indices: &[usize]) -> Vec<Option<&T>> {
+        let keys = sorted_keys(indices.iter().cloned(), self.len());
+        let mut result = vec![None; indices.len()];
+        finger_tree::lookup_many(&key_before, 0, &keys, self.inner(), &mut |chunk: &Chunk<T,K>, j, keys: &[(usize, usize)]| {
+            for &(i, pos) in keys {
+                result[pos] = chunk.get(i - j);
+            }
+        });
+        result
+    }
+
+    /// A new sequence with `func` applied to every element, in order. Time: *O(n)*
+    ///
+    /// The new sequence has the same shape as `self`, so no rebalancing is done, unlike `self.iter().map(func).collect()`.
+    pub fn map<U,F>(&self, mut func: F) -> Seq<U,K>
+        where U: Shared<K>,
+              F: FnMut(&T) -> U
+    {
+        Seq(strict(finger_tree::map(&mut move |chunk: &Chunk<T,K>| chunk.iter().map(&mut func).collect(), self.inner())))
+    }
+
+    /// A new sequence with `func` applied to every element, lazily. Time: *O(1)*
+    ///
+    /// Elements are computed at most once, when the nodes containing them are first forced, so mapping a large sequence and then reading a few elements only maps *O(log(n))* chunks per read. The result keeps the unmapped parts of `self` alive until they are evaluated.
+    ///
+    /// With the `strict` feature, this is the same as `map`.
+    ///
+    /// Unlike `map`, this needs `'static` elements and a `'static` function, since the result holds on to both until it is forced, and may outlive anything they borrow.
+    pub fn lazy_map<U,F>(&self, func: F) -> Seq<U,K>
+        where T: 'static,
+              U: Shared<K>,
+              F: Fn(&T) -> U + Shared<K> + 'static
+    {
+        Seq(finger_tree::lazy_map(&strict(move |chunk: &Chunk<T,K>| chunk.iter().map(&func).collect()), self.inner()))
+    }
+
+    /// A new sequence of `func(x, y)` for each pair of elements `x` and `y` at the same index in `self` and `other`, in order. Time: *O(n)*
+    ///
+    /// The new sequence is as long as the shorter of `self` and `other`, and has the same shape as the first part of `self`, as with `map`.
+    pub fn zip_with<U,V,F>(&self, other: &Seq<U,K>, mut func: F) -> Seq<V,K>
+        where U: Shared<K>,
+              V: Shared<K>,
+              F: FnMut(&T, &U) -> V
+    {
+        let mut others = other.iter();
+        self.truncate(other.len()).map(move |x| func(x, others.next().unwrap()))
+    }
+
+    /// A new sequence of `func(&state, x)` for each element `x`, in order, where `state` starts as `init`, and is then the previous element of the new sequence. Time: *O(n)*
+    ///
+    /// This is an inclusive prefix scan, such as running totals: element `i` is the result of combining `init` with the first `i + 1` elements, and `init` itself is not included. The new sequence has the same shape as `self`, as with `map`.
+    pub fn scan<S,F>(&self, init: S, mut func: F) -> Seq<S,K>
+        where S: Shared<K> + Clone,
+              F: FnMut(&S, &T) -> S
+    {
+        let mut state = init;
+        self.map(move |x| {
+            state = func(&state, x);
+            state.clone()
+        })
+    }
+
+    /// A new sequence with the elements of `func(x)` for every element `x`, in order. Time: *O(n + m)*, where *m* is the length of the result
+    ///
+    /// The elements are packed into full chunks as they are produced, rather than pushed one by one.
+    pub fn flat_map<U,I,F>(&self, mut func: F) -> Seq<U,K>
+        where U: Shared<K>,
+              I: IntoIterator<Item=U>,
+              F: FnMut(&T) -> I
+    {
+        let mut builder = Builder::new();
+        for x in self {
+            for y in func(x) {
+                builder.push(y);
+            }
+        }
+        builder.finish()
+    }
+
+    /// A new sequence of `k` copies of `self`, one after the other. Time: *O(log(k) log(nk))*
+    ///
+    /// The sequence is built by repeated doubling, appending each power of two copies to itself, so the nodes of `self` are shared between all of the copies, rather than copied.
+    pub fn cycle(&self, k: usize) -> Seq<T,K> {
+        let mut result = Seq::nil();
+        let mut power = self.clone();
+        let mut k = k;
+        while k > 0 {
+            if k % 2 == 1 {
+                result = result.append(&power);
+            }
+            k /= 2;
+            if k > 0 {
+                power = power.append(&power);
+            }
         }
+        result
     }
 
     /// An iterator over the sequence. Time: *O(1)*
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T, K> {
         self.into_iter()
     }
 
-    fn inner(&self) -> &Lazy<FingerTree<Item<T>,usize>> {
+    /// An iterator over the elements in `range`. Time: *O(log(n))*
+    ///
+    /// Panics if the start of the range is after its end, or the end is after the end of the sequence, like `VecDeque::range`.
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> Iter<'_, T, K> {
+        let (start, end) = bounds(range, self.len());
+        self.iter().split_at(end).0.split_at(start).1
+    }
+
+    /// An iterator over the elements of the sequence, as contiguous slices. Time: *O(1)*
+    ///
+    /// Each slice is the contents of one leaf of the tree; the slices are non-empty, and concatenating them gives the whole sequence.
+    pub fn slices(&self) -> Slices<'_, T, K> {
+        Slices {
+            inner: self.inner().iter(),
+        }
+    }
+
+    /// Add `x` to the front of `self`, in place. Time: *O(1)*
+    ///
+    /// Parts of the sequence that are not shared with any other sequence are modified in place, rather than copied.
+    pub fn push_front_mut(&mut self, x: T) {
+        let x = match Detached::front(&mut self.0) {
+            None => x,
+            Some(mut leaf) => match leaf.chunk().push_front_mut(x) {
+                Ok(()) => return,
+                Err(x) => x,
+            }
+        };
+        finger_tree::cons_node_mut(node::leaf(Chunk::single_front(x)), &mut self.0)
+    }
+
+    /// Add `x` to the back of `self`, in place. Time: *O(1)*
+    ///
+    /// Parts of the sequence that are not shared with any other sequence are modified in place, rather than copied.
+    pub fn push_back_mut(&mut self, x: T) {
+        let x = match Detached::back(&mut self.0) {
+            None => x,
+            Some(mut leaf) => match leaf.chunk().push_back_mut(x) {
+                Ok(()) => return,
+                Err(x) => x,
+            }
+        };
+        finger_tree::snoc_node_mut(&mut self.0, node::leaf(Chunk::single_back(x)))
+    }
+
+    /// A report on the shape of the tree, and how much of it has been evaluated. Time: *O(n)*
+    ///
+    /// Nothing is forced, so this can be used to see how much work a sequence has deferred.
+    pub fn stats(&self) -> SeqStats {
+        let mut walk = Walk::new();
+        finger_tree::stats(self.inner(), &mut walk);
+        walk.stats
+    }
+
+    /// The same as `stats`, with `shared` set to the number of thunks that `self` shares with `other`. Time: *O(n1 + n2)*
+    pub fn stats_shared_with(&self, other: &Seq<T,K>) -> SeqStats {
+        let mut walk = Walk::new();
+        finger_tree::stats(self.inner(), &mut walk);
+        let mut other_walk = Walk::new();
+        finger_tree::stats(other.inner(), &mut other_walk);
+        walk.shared_with(&other_walk)
+    }
+
+    /// The tree, in Graphviz's DOT language, for debugging. Time: *O(n)*
+    ///
+    /// Each node of the tree is labelled with its kind and size, and leaves with the number of elements in them. Nothing is forced: unevaluated thunks are drawn dashed, without their contents.
+    pub fn to_dot(&self) -> String {
+        Seq::versions_to_dot(&[self])
+    }
+
+    /// Several versions of a sequence, in Graphviz's DOT language, for debugging. Time: *O(n1 + n2 + ...)*
+    ///
+    /// The roots are numbered in the order they are given, and the nodes that are shared between versions, for example by the result of an `append` and its arguments, are drawn once and filled.
+    pub fn versions_to_dot(versions: &[&Seq<T,K>]) -> String {
+        let mut graph = Dot::new();
+        for seq in versions {
+            let root = graph.version();
+            finger_tree::dot(&root, seq.inner(), &mut graph);
+        }
+        graph.finish()
+    }
+
+    /// Check the internal invariants of the tree, for testing. Time: *O(n)*
+    ///
+    /// The measures cached in the tree should match its contents, the nodes in each level of the spine should be one higher than those in the level above, leaves should not be empty, and no thunk should be stuck part way through evaluation. This forces the whole sequence.
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        finger_tree::check_invariants(self.inner())?;
+        if self.slices().any(|slice| slice.is_empty()) {
+            return Err(InvariantError::EmptyLeaf)
+        }
+        Ok(())
+    }
+
+    fn inner(&self) -> &Lazy<FingerTree<Chunk<T,K>,usize,K>,K> {
         match *self {
             Seq(ref inner) => inner
         }
     }
 }
 
+/// The start and end of `range`, for a sequence of length `len`.
+///
+/// Panics if the range is out of bounds, with the same messages as indexing a slice.
+fn bounds<R: RangeBounds<usize>>(range: R, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&i) => i,
+        Bound::Excluded(&i) => i.checked_add(1).expect("attempted to index sequence from after maximum usize"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&i) => i.checked_add(1).expect("attempted to index sequence up to maximum usize"),
+        Bound::Excluded(&i) => i,
+        Bound::Unbounded => len,
+    };
+    if start > end {
+        panic!("sequence index starts at {} but ends at {}", start, end)
+    }
+    if end > len {
+        panic!("range end index {} out of range for sequence of length {}", end, len)
+    }
+    (start, end)
+}
+
+/// The indices below `len`, each paired with its position in `indices`, sorted by index, and then by position.
+fn sorted_keys<I: IntoIterator<Item=usize>>(indices: I, len: usize) -> Vec<(usize, usize)> {
+    let mut keys: Vec<(usize, usize)> = indices.into_iter()
+        .enumerate()
+        .filter(|&(_, i)| i < len)
+        .map(|(pos, i)| (i, pos))
+        .collect();
+    if keys.windows(2).any(|w| w[0] > w[1]) {
+        keys.sort_unstable();
+    }
+    keys
+}
+
+/// Does the index in `key` come before the measure `j`?
+fn key_before(&(i, _): &(usize, usize), j: usize) -> bool {
+    i < j
+}
+
+/// A node of the tree of a sequence.
+type ChunkNode<T,K> = Lazy<Node<Chunk<T,K>,usize,K>,K>;
+
+/// Builds a sequence from elements and whole nodes, added in order, packing the elements into full chunks.
+struct Builder<T,K: Sharing> {
+    tree: Lazy<FingerTree<Chunk<T,K>,usize,K>,K>,
+    buffer: Vec<T>,
+}
+
+impl<T: Shared<K>, K: Sharing> Builder<T,K> {
+    fn new() -> Builder<T,K> {
+        Builder {
+            tree: finger_tree::empty(),
+            buffer: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, x: T) {
+        self.buffer.push(x);
+        if self.buffer.len() == CHUNK_SIZE {
+            self.flush();
+        }
+    }
+
+    /// Add the elements below `node`, which is `height` levels above the leaves, sharing the node rather than copying it.
+    fn push_node(&mut self, node: ChunkNode<T,K>, height: usize) {
+        self.flush();
+        if height == 0 {
+            finger_tree::snoc_node_mut(&mut self.tree, node);
+        } else {
+            let tree = mem::replace(&mut self.tree, finger_tree::empty());
+            self.tree = finger_tree::tree_tree(tree, finger_tree::from_node(node, height));
+        }
+    }
+
+    /// Add the buffered elements to the tree, as a chunk.
+    fn flush(&mut self) {
+        if !self.buffer.is_empty() {
+            let chunk = self.buffer.drain(..).collect();
+            finger_tree::snoc_node_mut(&mut self.tree, node::leaf(chunk));
+        }
+    }
+
+    fn finish(mut self) -> Seq<T,K> {
+        self.flush();
+        Seq(self.tree)
+    }
+}
+
+/// The sequences of the elements for which a predicate returns `true` and `false`, or `None` for a side that is dropped.
+type Sides<T,K> = (Option<Seq<T,K>>, Option<Seq<T,K>>);
+
+/// Sorts the elements of a sequence into two sides, by a predicate, sharing the nodes all of whose elements go to the same side.
+struct Sorter<'a, T: 'a, P, K: Sharing> {
+    pred: P,
+    /// The builders for the elements for which `pred` returns `false` and `true`, or `None` if those elements are dropped.
+    sides: [Option<Builder<T,K>>; 2],
+    /// The nodes all of whose elements go to the same side, with their heights and that side, which have not been added to a builder yet.
+    ///
+    /// A node replaces its children here if they all go to the same side, so only the largest such nodes are added.
+    pending: Vec<(&'a ChunkNode<T,K>, usize, bool)>,
+    /// The sides of the elements of the current chunk.
+    chunk_sides: Vec<bool>,
+    /// Have the elements of any chunk gone to both sides?
+    mixed: bool,
+}
+
+impl<'a, T: Shared<K> + Clone, K: Sharing, P> Sorter<'a, T, P, K>
+    where P: FnMut(&'a T) -> bool
+{
+    fn new(pred: P, keep_true: bool, keep_false: bool) -> Sorter<'a, T, P, K> {
+        Sorter {
+            pred,
+            sides: [if keep_false { Some(Builder::new()) } else { None },
+                    if keep_true { Some(Builder::new()) } else { None }],
+            pending: Vec::new(),
+            chunk_sides: Vec::with_capacity(CHUNK_SIZE),
+            mixed: false,
+        }
+    }
+
+    /// The sequences of the elements of `seq` for which `pred` returns `true` and `false`, if they are kept.
+    fn sort(mut self, seq: &'a Seq<T,K>) -> Sides<T,K> {
+        self.tree(seq.inner(), 0);
+        if !self.mixed {
+            let mut sides = self.pending.iter().map(|&(_, _, side)| side);
+            if let Some(side) = sides.next() {
+                if sides.all(|s| s == side) {
+                    // Every element went to the same side.
+                    let (all, none) = if side { (seq.clone(), Seq::nil()) } else { (Seq::nil(), seq.clone()) };
+                    return (self.sides[1].as_ref().map(|_| all), self.sides[0].as_ref().map(|_| none))
+                }
+            }
+        }
+        self.flush();
+        let [rest, matching] = self.sides;
+        (matching.map(Builder::finish), rest.map(Builder::finish))
+    }
+
+    /// Sort the elements of `tree`, whose digits hold nodes `height` levels above the leaves.
+    fn tree(&mut self, tree: &'a Lazy<FingerTree<Chunk<T,K>,usize,K>,K>, height: usize) {
+        match **tree {
+            Empty => {},
+            Single(ref node) => {
+                self.node(node, height);
+            },
+            Deep(_, ref left, ref middle, ref right) => {
+                for node in left.nodes() {
+                    self.node(node, height);
+                }
+                self.tree(middle, height + 1);
+                for node in right.nodes() {
+                    self.node(node, height);
+                }
+            },
+        }
+    }
+
+    /// Sort the elements below `node`, which is `height` levels above the leaves, returning the side they all went to, if they all went to the same side.
+    fn node(&mut self, node: &'a ChunkNode<T,K>, height: usize) -> Option<bool> {
+        let (side, children) = match **node {
+            Leaf(ref chunk) => (self.leaf(chunk), 0),
+            Node2(_, ref left, ref right) => {
+                let left = self.node(left, height - 1);
+                let right = self.node(right, height - 1);
+                (same(left, right), 2)
+            },
+            Node3(_, ref left, ref middle, ref right) => {
+                let left = self.node(left, height - 1);
+                let middle = self.node(middle, height - 1);
+                let right = self.node(right, height - 1);
+                (same(same(left, middle), right), 3)
+            },
+        };
+        if let Some(side) = side {
+            // The children are the last pending nodes, since nothing below this node was split between the sides.
+            let n = self.pending.len() - children;
+            self.pending.truncate(n);
+            self.pending.push((node, height, side));
+        }
+        side
+    }
+
+    /// Sort the elements of `chunk`, returning the side they all went to, if they all went to the same side.
+    fn leaf(&mut self, chunk: &'a Chunk<T,K>) -> Option<bool> {
+        self.chunk_sides.clear();
+        for x in chunk.iter() {
+            let side = (self.pred)(x);
+            self.chunk_sides.push(side);
+        }
+        let first = self.chunk_sides[0];
+        if self.chunk_sides.iter().all(|&side| side == first) {
+            return Some(first)
+        }
+        self.mixed = true;
+        self.flush();
+        for (x, &side) in chunk.iter().zip(&self.chunk_sides) {
+            if let Some(ref mut builder) = self.sides[side as usize] {
+                builder.push(x.clone());
+            }
+        }
+        None
+    }
+
+    /// Add the pending nodes to the builders for their sides.
+    fn flush(&mut self) {
+        for (node, height, side) in self.pending.drain(..) {
+            if let Some(ref mut builder) = self.sides[side as usize] {
+                builder.push_node(node.clone(), height);
+            }
+        }
+    }
+}
+
+/// The side that both `a` and `b` went to, if they went to the same side.
+fn same(a: Option<bool>, b: Option<bool>) -> Option<bool> {
+    match (a, b) {
+        (Some(a), Some(b)) if a == b => Some(a),
+        _ => None,
+    }
+}
+
+impl<A, B, K: Sharing> Seq<(A, B), K>
+    where A: Shared<K> + Clone,
+          B: Shared<K> + Clone,
+          (A, B): Shared<K>
+{
+    /// Two new sequences, of the first and second elements of each pair, in order. Time: *O(n)*
+    ///
+    /// Both have the same shape as `self`, as with `map`.
+    pub fn unzip(&self) -> (Seq<A,K>, Seq<B,K>) {
+        (self.map(|pair| pair.0.clone()), self.map(|pair| pair.1.clone()))
+    }
+}
+
+/// A copy of `chunk`, with its elements in reverse order.
+fn reverse_chunk<T: Clone, K: Sharing>(chunk: &Chunk<T,K>) -> Chunk<T,K> {
+    chunk.iter().rev().cloned().collect()
+}
+
+impl<T: Shared + Clone> Seq<T> {
+    /// A sequence of `n` copies of `x`. Time: *O(log²(n))*
+    ///
+    /// `x` is cloned to fill one chunk, and the sequence is built by doubling, as by `cycle`, so the chunk and most of the nodes are shared between the copies. Each of the *O(log(n))* doublings is an append, which takes *O(log(n))* time and adds as many nodes, so the sequence takes *O(log²(n))* space, until it is modified.
+    pub fn repeat(x: T, n: usize) -> Seq<T> {
+        Seq::repeated(x, n)
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Shared<Atomic> + Clone> Seq<T, Atomic> {
+    /// A sequence of `n` copies of `x`, which can be shared between threads; see `Seq::repeat`.
+    pub fn repeat_sync(x: T, n: usize) -> SyncSeq<T> {
+        Seq::repeated(x, n)
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> Seq<T,K>
+    where T: Clone
+{
+    /// Remove the front element of `self`, in place, returning it if it exists. Time: *O(1)*
+    ///
+    /// Parts of the sequence that are not shared with any other sequence are modified in place, rather than copied. The element is only cloned if it is shared.
+    pub fn pop_front_mut(&mut self) -> Option<T> {
+        Detached::front(&mut self.0).and_then(|mut leaf| leaf.chunk().pop_front_mut())
+    }
+
+    /// Remove the back element of `self`, in place, returning it if it exists. Time: *O(1)*
+    ///
+    /// Parts of the sequence that are not shared with any other sequence are modified in place, rather than copied. The element is only cloned if it is shared.
+    pub fn pop_back_mut(&mut self) -> Option<T> {
+        Detached::back(&mut self.0).and_then(|mut leaf| leaf.chunk().pop_back_mut())
+    }
+
+    /// A sequence of `n` copies of `x`, with either kind of sharing; see `Seq::repeat`.
+    fn repeated(x: T, n: usize) -> Seq<T,K> {
+        let mut chunk = Seq::nil();
+        chunk.extend(iter::repeat_n(x, cmp::min(n, CHUNK_SIZE)));
+        chunk.cycle(n / CHUNK_SIZE).append(&chunk.truncate(n % CHUNK_SIZE))
+    }
+
+    /// A new sequence of the pairs of elements at the same index in `self` and `other`, in order. Time: *O(n)*
+    ///
+    /// The new sequence is as long as the shorter of `self` and `other`; see `zip_with`.
+    pub fn zip<U>(&self, other: &Seq<U,K>) -> Seq<(T, U), K>
+        where U: Shared<K> + Clone,
+              (T, U): Shared<K>
+    {
+        self.zip_with(other, |x, y| (x.clone(), y.clone()))
+    }
+
+    /// A new sequence of the elements for which `pred` returns `true`, in order. Time: *O(n)*
+    ///
+    /// `pred` is called once for each element, in order. The nodes all of whose elements are kept are shared with `self`, rather than copied, so filtering out a few elements keeps most of the tree, and keeping every element returns a clone of `self`. The other elements are cloned, and packed into full chunks.
+    pub fn filter<F>(&self, pred: F) -> Seq<T,K>
+        where F: FnMut(&T) -> bool
+    {
+        let (kept, _) = Sorter::new(pred, true, false).sort(self);
+        kept.unwrap()
+    }
+
+    /// Keep only the elements for which `pred` returns `true`, as `filter` does. Time: *O(n)*
+    pub fn retain<F>(&mut self, pred: F)
+        where F: FnMut(&T) -> bool
+    {
+        *self = self.filter(pred);
+    }
+
+    /// Two new sequences, of the elements for which `pred` returns `true`, and of those for which it returns `false`, in order. Time: *O(n)*
+    ///
+    /// As with `filter`, the nodes all of whose elements go to the same side are shared with `self`.
+    pub fn partition<F>(&self, pred: F) -> (Seq<T,K>, Seq<T,K>)
+        where F: FnMut(&T) -> bool
+    {
+        let (matching, rest) = Sorter::new(pred, true, true).sort(self);
+        (matching.unwrap(), rest.unwrap())
+    }
+
+    /// A new sequence without the consecutive elements that have the same key as the element before them. Time: *O(n)*
+    ///
+    /// `key` is called once for each element, in order. As with `filter`, the nodes that have no such elements are shared with `self`.
+    pub fn dedup_by_key<Q,F>(&self, mut key: F) -> Seq<T,K>
+        where Q: PartialEq,
+              F: FnMut(&T) -> Q
+    {
+        let mut last = None;
+        self.filter(move |x| {
+            let k = key(x);
+            let keep = last.as_ref() != Some(&k);
+            last = Some(k);
+            keep
+        })
+    }
+
+    /// A new sequence without the consecutive elements that are equal to the element before them. Time: *O(n)*
+    pub fn dedup(&self) -> Seq<T,K>
+        where T: PartialEq
+    {
+        let mut last: Option<&T> = None;
+        let (kept, _) = Sorter::new(|x| {
+            let keep = last != Some(x);
+            last = Some(x);
+            keep
+        }, true, false).sort(self);
+        kept.unwrap()
+    }
+
+    /// A new sequence with the element at each of `indices` replaced by `func` of it. Time: *O(k log(n/k))* for *k* sorted indices
+    ///
+    /// This is the same as calling `adjust` for each index in turn, so `func` is applied twice to an element whose index appears twice, but the tree is walked once, and each chunk of elements is copied at most once. Indices that are out of range are ignored, and those that are not sorted are sorted first, in *O(k log(k))* time.
+    pub fn adjust_many<I,F>(&self, indices: I, mut func: F) -> Seq<T,K>
+        where I: IntoIterator<Item=usize>,
+              F: FnMut(&T) -> T
+    {
+        let keys = sorted_keys(indices, self.len());
+        self.adjust_keys(&keys, |_, x| func(x))
+    }
+
+    /// A new sequence with the element at each index `i` replaced by `x`, for each `(i, x)` in `updates`. Time: *O(k log(n/k))* for *k* updates sorted by index
+    ///
+    /// This is the same as calling `update` for each pair in turn, so the last update to an index wins, but the tree is walked once. Updates that are out of range are ignored, and those that are not sorted are sorted first, in *O(k log(k))* time.
+    pub fn update_many<I>(&self, updates: I) -> Seq<T,K>
+        where I: IntoIterator<Item=(usize, T)>
+    {
+        let mut values = Vec::new();
+        let keys = sorted_keys(updates.into_iter().map(|(i, x)| {
+            values.push(Some(x));
+            i
+        }), self.len());
+        self.adjust_keys(&keys, |pos, _| values[pos].take().expect("each update is used once"))
+    }
+
+    /// A new sequence with the element at index `i` replaced by `func(pos, self[i])`, for each `(i, pos)` in the sorted `keys`.
+    fn adjust_keys<F>(&self, keys: &[(usize, usize)], mut func: F) -> Seq<T,K>
+        where F: FnMut(usize, &T) -> T
+    {
+        let mut adjust = |chunk: &Chunk<T,K>, j, keys: &[(usize, usize)]| {
+            let mut elements = chunk.to_vec();
+            for &(i, pos) in keys {
+                let x = func(pos, &elements[i - j]);
+                elements[i - j] = x;
+            }
+            elements.into_iter().collect()
+        };
+        Seq(finger_tree::adjust_many(&mut adjust, &key_before, 0, keys, self.inner()))
+    }
+
+    /// A new sequence with the elements of `self` in reverse order. Time: *O(1)*
+    ///
+    /// The reversal is lazy, like `lazy_map`: each node of the result is reversed when it is first forced, so reading the front, the back, or a single element only reverses the *O(log(n))* nodes on the way to it, and copies the few chunks of elements next to it. Reading the whole result costs *O(n)*, once. The result is an ordinary sequence, which can be appended to any other.
+    ///
+    /// With the `strict` feature, this takes *O(n)* time.
+    pub fn reverse(&self) -> Seq<T,K> {
+        Seq(finger_tree::reverse(reverse_chunk::<T, K>, self.inner()))
+    }
+
+    /// A mutable reference to the element at index `i`, if it exists. Time: *O(log(min(i,n-i)))*
+    ///
+    /// The nodes on the path to the element, and the chunk containing it, are copied if they are shared with another sequence (copy-on-write), so other sequences are not affected by changes made through the reference.
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        if i >= self.len() {
+            return None
+        }
+        let (chunk, j) = finger_tree::lookup_mut(move |j| {i < j}, 0, &mut self.0);
+        Some(chunk.get_mut(i - j))
+    }
+}
+
+/// A leaf taken from one end of a tree by an in-place operation.
+///
+/// The leaf is put back when this is dropped, unless it has become empty, so its elements are not lost if the operation panics part way through.
+struct Detached<'a, T: Shared<K>, K: Sharing> {
+    tree: &'a mut Lazy<FingerTree<Chunk<T,K>,usize,K>,K>,
+    leaf: Option<ChunkNode<T,K>>,
+    back: bool,
+}
+
+impl<'a, T: Shared<K>, K: Sharing> Detached<'a, T, K> {
+    fn front(tree: &'a mut Lazy<FingerTree<Chunk<T,K>,usize,K>,K>) -> Option<Detached<'a, T, K>> {
+        finger_tree::viewl_node_mut(tree).map(move |leaf| Detached {
+            tree,
+            leaf: Some(leaf),
+            back: false,
+        })
+    }
+
+    fn back(tree: &'a mut Lazy<FingerTree<Chunk<T,K>,usize,K>,K>) -> Option<Detached<'a, T, K>> {
+        finger_tree::viewr_node_mut(tree).map(move |leaf| Detached {
+            tree,
+            leaf: Some(leaf),
+            back: true,
+        })
+    }
+
+    fn chunk(&mut self) -> &mut Chunk<T,K> {
+        match *self.leaf.as_mut().unwrap().make_mut() {
+            Leaf(ref mut chunk) => chunk,
+            _ => unreachable!(),
+        }
+    }
+
+    fn reattach(&mut self) {
+        let leaf = match self.leaf.take() {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        // A leaf that could not be evaluated has no elements to keep.
+        match leaf.try_force() {
+            Ok(node) if !node::leaf_value(node).is_empty() => {},
+            _ => return,
+        }
+        if self.back {
+            finger_tree::snoc_node_mut(self.tree, leaf)
+        } else {
+            finger_tree::cons_node_mut(leaf, self.tree)
+        }
+    }
+}
+
+impl<'a, T: Shared<K>, K: Sharing> Drop for Detached<'a, T, K> {
+    #[cfg(feature = "std")]
+    fn drop(&mut self) {
+        if thread::panicking() {
+            // Putting the leaf back may force other parts of the tree, and a
+            // second panic would abort.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| self.reattach()));
+        } else {
+            self.reattach()
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn drop(&mut self) {
+        self.reattach()
+    }
+}
+
 /// Creates a `Seq` containing the arguments
 ///
 /// ```
@@ -188,7 +1122,7 @@ impl<T:'static> Seq<T> {
 /// # }
 /// ```
 ///
-/// Alternatively, a `Seq` consisting of several copies of the same value can be created using the following syntax:
+/// Alternatively, a `Seq` consisting of several copies of the same value can be created using the following syntax, which uses `Seq::repeat`, and so takes *O(log²(n))* time and space:
 ///
 /// ```
 /// # #[macro_use]
@@ -208,45 +1142,135 @@ macro_rules! seq {
         seq!($($e),*).push_front($e0)
     };
     ($e: expr ; $n: expr) => {
-        ::std::iter::repeat($e).take($n).collect::<$crate::Seq<_>>()
+        $crate::Seq::repeat($e, $n)
     };
 }
 
-impl<T:'static> Clone for Seq<T> {
-    fn clone(&self) -> Seq<T> {
+impl<T: Shared<K>, K: Sharing> Clone for Seq<T,K> {
+    fn clone(&self) -> Seq<T,K> {
         Seq(self.inner().clone())
     }
 }
 
-impl<T:'static> PartialEq for Seq<T>
+impl<T: Shared> Default for Seq<T> {
+    fn default() -> Seq<T> {
+        Seq::empty()
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> PartialEq for Seq<T,K>
     where T: PartialEq
 {
-    fn eq(&self, other: &Seq<T>) -> bool {
+    fn eq(&self, other: &Seq<T,K>) -> bool {
         self.iter().eq(other.iter())
     }
 }
 
-impl<T:'static> Eq for Seq<T>
+impl<T: Shared<K>, K: Sharing> Eq for Seq<T,K>
     where T: Eq
 {}
 
-impl<T:'static> PartialOrd for Seq<T>
+impl<T: Shared<K>, K: Sharing, U> PartialEq<[U]> for Seq<T,K>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &[U]) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<'a, T: Shared<K>, K: Sharing, U> PartialEq<&'a [U]> for Seq<T,K>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &&'a [U]) -> bool {
+        *self == **other
+    }
+}
+
+impl<T: Shared<K>, K: Sharing, U, const N: usize> PartialEq<[U; N]> for Seq<T,K>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &[U; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Shared<K>, K: Sharing, U> PartialEq<Vec<U>> for Seq<T,K>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &Vec<U>) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Shared<K>, K: Sharing, U> PartialEq<VecDeque<U>> for Seq<T,K>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &VecDeque<U>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T, K: Sharing, U: Shared<K>> PartialEq<Seq<U,K>> for [T]
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &Seq<U,K>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T, K: Sharing, U: Shared<K>> PartialEq<Seq<U,K>> for &[T]
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &Seq<U,K>) -> bool {
+        **self == *other
+    }
+}
+
+impl<T, K: Sharing, U: Shared<K>> PartialEq<Seq<U,K>> for Vec<T>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &Seq<U,K>) -> bool {
+        self[..] == *other
+    }
+}
+
+impl<T, K: Sharing, U: Shared<K>> PartialEq<Seq<U,K>> for VecDeque<T>
+    where T: PartialEq<U>
+{
+    fn eq(&self, other: &Seq<U,K>) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> PartialOrd for Seq<T,K>
     where T: PartialOrd
 {
-    fn partial_cmp(&self, other: &Seq<T>) -> Option<cmp::Ordering> {
+    fn partial_cmp(&self, other: &Seq<T,K>) -> Option<cmp::Ordering> {
         self.iter().partial_cmp(other.iter())
     }
 }
 
-impl<T:'static> Ord for Seq<T>
+impl<T: Shared<K>, K: Sharing> Ord for Seq<T,K>
     where T: Ord
 {
-    fn cmp(&self, other: &Seq<T>) -> cmp::Ordering {
+    fn cmp(&self, other: &Seq<T,K>) -> cmp::Ordering {
         self.iter().cmp(other.iter())
     }
 }
 
-impl<T:'static> fmt::Debug for Seq<T>
+/// Hashes the length and then the elements, so equal sequences have equal hashes, however their trees are shaped.
+impl<T: Shared<K>, K: Sharing> Hash for Seq<T,K>
+    where T: Hash
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for x in self {
+            x.hash(state);
+        }
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> fmt::Debug for Seq<T,K>
     where T: fmt::Debug
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -255,107 +1279,374 @@ impl<T:'static> fmt::Debug for Seq<T>
 }
 
 #[derive(Debug)]
-pub struct Iter<'a, T: 'a> {
-    inner: finger_tree::Iter<'a, Item<T>, usize>
+pub struct Iter<'a, T: 'a, K: Sharing = Local> {
+    chunks: Slices<'a, T, K>,
+    front: slice::Iter<'a, T>,
+    back: slice::Iter<'a, T>,
+    len: usize,
 }
 
-impl<'a,T:'static> Iter<'a,T> {
-    fn new(seq: &'a Seq<T>) -> Iter<'a,T> {
+impl<'a,T: Shared<K>,K: Sharing> Iter<'a,T,K> {
+    fn new(seq: &'a Seq<T,K>) -> Iter<'a,T,K> {
         Iter {
-            inner: seq.inner().iter()
+            chunks: seq.slices(),
+            front: [].iter(),
+            back: [].iter(),
+            len: seq.len(),
+        }
+    }
+}
+
+impl<'a,T:'a,K: Sharing> Iter<'a,T,K> {
+    /// Two iterators, over the first `n` remaining elements and the rest, respectively. Time: *O(log(n))*
+    ///
+    /// `n` must be at most the number of remaining elements.
+    pub(crate) fn split_at(self, n: usize) -> (Iter<'a,T,K>, Iter<'a,T,K>) {
+        let front = self.front.as_slice();
+        let back = self.back.as_slice();
+        if n <= front.len() {
+            return (Iter { chunks: Slices::empty(), front: front[..n].iter(), back: [].iter(), len: n },
+                    Iter { chunks: self.chunks, front: front[n..].iter(), back: back.iter(), len: self.len - n })
+        }
+        let m = n - front.len();
+        if m >= self.len - front.len() - back.len() {
+            let k = n - (self.len - back.len());
+            return (Iter { chunks: self.chunks, front: front.iter(), back: back[..k].iter(), len: n },
+                    Iter { chunks: Slices::empty(), front: back[k..].iter(), back: [].iter(), len: self.len - n })
         }
+        let (before, mut after) = self.chunks.inner.split(&move |i| {m < i}, 0);
+        let k = m - before.measure();
+        let middle = match after.next() {
+            Some(chunk) => chunk.as_slice(),
+            None => &[],
+        };
+        (Iter { chunks: Slices { inner: before }, front: front.iter(), back: middle[..k].iter(), len: n },
+         Iter { chunks: Slices { inner: after }, front: middle[k..].iter(), back: back.iter(), len: self.len - n })
     }
 }
 
-impl<'a,T:'a> Iterator for Iter<'a,T> {
+impl<'a,T:'a,K: Sharing> Iterator for Iter<'a,T,K> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<&'a T> {
-        match self.inner.next() {
-            None => None,
-            Some(&Item(ref x)) => Some(x)
+        loop {
+            if let Some(x) = self.front.next() {
+                self.len -= 1;
+                return Some(x)
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.front = chunk.iter(),
+                None => break,
+            }
+        }
+        let x = self.back.next();
+        if x.is_some() {
+            self.len -= 1;
+        }
+        x
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a,T:'a,K: Sharing> DoubleEndedIterator for Iter<'a,T,K> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(x) = self.back.next_back() {
+                self.len -= 1;
+                return Some(x)
+            }
+            match self.chunks.next_back() {
+                Some(chunk) => self.back = chunk.iter(),
+                None => break,
+            }
         }
+        let x = self.front.next_back();
+        if x.is_some() {
+            self.len -= 1;
+        }
+        x
+    }
+}
+
+impl<'a,T:'a,K: Sharing> ExactSizeIterator for Iter<'a,T,K> {}
+
+/// An iterator over the chunks of a `Seq`, as slices.
+#[derive(Debug)]
+pub struct Slices<'a, T: 'a, K: Sharing = Local> {
+    inner: finger_tree::Iter<'a, Chunk<T,K>, usize, K>,
+}
+
+impl<'a,T:'a,K: Sharing> Slices<'a,T,K> {
+    fn empty() -> Slices<'a,T,K> {
+        Slices {
+            inner: finger_tree::Iter::empty(),
+        }
+    }
+}
+
+impl<'a,T:'a,K: Sharing> Iterator for Slices<'a,T,K> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<&'a [T]> {
+        self.inner.next().map(|chunk| chunk.as_slice())
+    }
+}
+
+impl<'a,T:'a,K: Sharing> DoubleEndedIterator for Slices<'a,T,K> {
+    fn next_back(&mut self) -> Option<&'a [T]> {
+        self.inner.next_back().map(|chunk| chunk.as_slice())
     }
 }
 
-impl<'a, T: 'static> iter::IntoIterator for &'a Seq<T> {
+impl<'a, T: Shared<K>, K: Sharing> iter::IntoIterator for &'a Seq<T,K> {
     type Item = &'a T;
 
-    type IntoIter = Iter<'a, T>;
+    type IntoIter = Iter<'a, T, K>;
 
-    fn into_iter(self) -> Iter<'a,T> {
+    fn into_iter(self) -> Iter<'a,T,K> {
         Iter::new(self)
     }
 }
 
-impl<T:'static> iter::FromIterator<T> for Seq<T> {
+/// An iterator over the parts of a `Seq`, as sequences; see `Seq::chunks`.
+#[derive(Debug)]
+pub struct Chunks<T: Shared<K>, K: Sharing = Local> {
+    rest: Seq<T,K>,
+    size: usize,
+}
+
+impl<T: Shared<K>, K: Sharing> Iterator for Chunks<T,K> {
+    type Item = Seq<T,K>;
+
+    fn next(&mut self) -> Option<Seq<T,K>> {
+        if self.rest.is_empty() {
+            return None
+        }
+        let (chunk, rest) = self.rest.split(self.size);
+        self.rest = rest;
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.rest.len().div_ceil(self.size);
+        (n, Some(n))
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> DoubleEndedIterator for Chunks<T,K> {
+    fn next_back(&mut self) -> Option<Seq<T,K>> {
+        if self.rest.is_empty() {
+            return None
+        }
+        let len = self.rest.len();
+        let last = match len % self.size {
+            0 => self.size,
+            k => k,
+        };
+        let (rest, chunk) = self.rest.split(len - last);
+        self.rest = rest;
+        Some(chunk)
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> ExactSizeIterator for Chunks<T,K> {}
+
+/// An iterator over the overlapping runs of elements of a `Seq`, as sequences; see `Seq::windows`.
+#[derive(Debug)]
+pub struct Windows<T: Shared<K>, K: Sharing = Local> {
+    seq: Seq<T,K>,
+    size: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<T: Shared<K>, K: Sharing> Iterator for Windows<T,K> {
+    type Item = Seq<T,K>;
+
+    fn next(&mut self) -> Option<Seq<T,K>> {
+        if self.start >= self.end {
+            return None
+        }
+        let window = self.seq.subseq(self.start..self.start + self.size);
+        self.start += 1;
+        Some(window)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end.saturating_sub(self.start);
+        (n, Some(n))
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> DoubleEndedIterator for Windows<T,K> {
+    fn next_back(&mut self) -> Option<Seq<T,K>> {
+        if self.start >= self.end {
+            return None
+        }
+        self.end -= 1;
+        Some(self.seq.subseq(self.end..self.end + self.size))
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> ExactSizeIterator for Windows<T,K> {}
+
+/// An iterator that moves the elements out of a `Seq`.
+///
+/// Elements are moved out of the parts of the sequence that are not shared with any other sequence, and cloned from the rest, as by `pop_front_mut`.
+#[derive(Debug)]
+pub struct IntoIter<T: Shared<K>, K: Sharing = Local> {
+    seq: Seq<T,K>,
+}
+
+impl<T: Shared<K> + Clone, K: Sharing> Iterator for IntoIter<T,K> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.seq.pop_front_mut()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.seq.len(), Some(self.seq.len()))
+    }
+}
+
+impl<T: Shared<K> + Clone, K: Sharing> DoubleEndedIterator for IntoIter<T,K> {
+    fn next_back(&mut self) -> Option<T> {
+        self.seq.pop_back_mut()
+    }
+}
+
+impl<T: Shared<K> + Clone, K: Sharing> ExactSizeIterator for IntoIter<T,K> {}
+
+impl<T: Shared<K> + Clone, K: Sharing> iter::IntoIterator for Seq<T,K> {
+    type Item = T;
+
+    type IntoIter = IntoIter<T,K>;
+
+    fn into_iter(self) -> IntoIter<T,K> {
+        IntoIter {
+            seq: self,
+        }
+    }
+}
+
+impl<T: Shared> iter::FromIterator<T> for Seq<T> {
     fn from_iter<I>(iter: I) -> Self
         where I: IntoIterator<Item=T> {
-        let mut iter = iter.into_iter();
         let mut seq = Seq::empty();
-        while let Some(x) = iter.next() {
-            seq = seq.push_back(x);
-        }
+        seq.extend(iter);
         seq
     }
 }
 
-impl<T:'static> convert::From<Vec<T>> for Seq<T> {
+impl<'a, T: Shared + Clone + 'a> iter::FromIterator<&'a T> for Seq<T> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item=&'a T> {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<T: Shared<Atomic>> iter::FromIterator<T> for Seq<T, Atomic> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item=T> {
+        let mut seq = Seq::empty_sync();
+        seq.extend(iter);
+        seq
+    }
+}
+
+#[cfg(feature = "sync")]
+impl<'a, T: Shared<Atomic> + Clone + 'a> iter::FromIterator<&'a T> for Seq<T, Atomic> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item=&'a T> {
+        iter.into_iter().cloned().collect()
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> Extend<T> for Seq<T,K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item=T> {
+        for x in iter {
+            self.push_back_mut(x);
+        }
+    }
+}
+
+impl<'a, T: Shared<K> + Clone + 'a, K: Sharing> Extend<&'a T> for Seq<T,K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item=&'a T> {
+        self.extend(iter.into_iter().cloned())
+    }
+}
+
+/// Concatenates the sequences. Time: *O(k log(n))* for *k* sequences.
+impl<T: Shared<K>, K: Sharing> iter::Sum for Seq<T,K> {
+    fn sum<I>(iter: I) -> Seq<T,K>
+        where I: Iterator<Item=Seq<T,K>> {
+        iter.fold(Seq::nil(), |acc, seq| acc.append(&seq))
+    }
+}
+
+/// Concatenates the sequences. Time: *O(k log(n))* for *k* sequences.
+impl<'a, T: Shared<K> + 'a, K: Sharing> iter::Sum<&'a Seq<T,K>> for Seq<T,K> {
+    fn sum<I>(iter: I) -> Seq<T,K>
+        where I: Iterator<Item=&'a Seq<T,K>> {
+        iter.fold(Seq::nil(), |acc, seq| acc.append(seq))
+    }
+}
+
+impl<T: Shared> convert::From<Vec<T>> for Seq<T> {
     fn from(v: Vec<T>) -> Seq<T> {
         v.into_iter().collect()
     }
 }
 
-impl<T:'static> ops::Index<usize> for Seq<T> {
+impl<T: Shared> convert::From<VecDeque<T>> for Seq<T> {
+    fn from(v: VecDeque<T>) -> Seq<T> {
+        v.into_iter().collect()
+    }
+}
+
+impl<T: Shared, const N: usize> convert::From<[T; N]> for Seq<T> {
+    fn from(a: [T; N]) -> Seq<T> {
+        IntoIterator::into_iter(a).collect()
+    }
+}
+
+impl<'a, T: Shared + Clone> convert::From<&'a [T]> for Seq<T> {
+    fn from(s: &'a [T]) -> Seq<T> {
+        s.iter().collect()
+    }
+}
+
+/// Moves the elements that are not shared with another sequence, and clones the rest; see `IntoIter`.
+impl<T: Shared<K> + Clone, K: Sharing> convert::From<Seq<T,K>> for Vec<T> {
+    fn from(seq: Seq<T,K>) -> Vec<T> {
+        seq.into_iter().collect()
+    }
+}
+
+/// Moves the elements that are not shared with another sequence, and clones the rest; see `IntoIter`.
+impl<T: Shared<K> + Clone, K: Sharing> convert::From<Seq<T,K>> for VecDeque<T> {
+    fn from(seq: Seq<T,K>) -> VecDeque<T> {
+        seq.into_iter().collect()
+    }
+}
+
+impl<T: Shared<K>, K: Sharing> ops::Index<usize> for Seq<T,K> {
     type Output = T;
     fn index(&self, index: usize) -> &T {
         self.get(index).expect("Out of bounds access")
     }
 }
 
-// impl<T> ops::Index<ops::Range<usize>> for Seq<T> {
-//     type Output = Seq<T>;
-//     fn index(&self, index: ops::Range<usize>) -> &Seq<T> {
-//         if index.start >= index.end {
-//             Seq::empty()
-//         } else if index.start == 0 {
-//             self.index(ops::RangeTo(index.end))
-//         } else if index.end >= self.len() {
-//             self.index(ops::RangeFrom(index.start))
-//         } else {
-//             self.truncate(index.end).truncate_front(index.end - index.start)
-//         }
-//     }
-// }
-
-// impl<T> ops::Index<ops::RangeTo<usize>> for Seq<T> {
-//     type Output = Seq<T>;
-//     fn index(&self, index: ops::RangeTo<usize>) -> &Seq<T> {
-//         if index.end >= self.len() {
-//             self.index(ops::RangeFull)
-//         } else {
-//             self.truncate(index.end)
-//         }
-//     }
-// }
-
-// impl<T> ops::Index<ops::RangeFrom<usize>> for Seq<T> {
-//     type Output = Seq<T>;
-//     fn index(&self, index: ops::RangeFrom<usize>) -> &Seq<T> {
-//         if index.begin >= self.len() {
-//             Seq::empty()
-//         }
-//         if index.begin == 0 {
-//             self.index(ops::RangeFull)
-//         } else {
-//             self.truncate_front(self.len() - index.start)
-//         }
-//     }
-// }
-
-// impl<T> ops::Index<ops::RangeFull> for Seq<T> {
-//     type Output = Seq<T>;
-//     fn index(&self, index: ops::RangeFull) -> &Seq<T> {
-//         self.clone()
-//     }
-// }
+/// Copies the path to the element, and the chunk containing it, if they are shared; see `get_mut`.
+impl<T: Shared<K> + Clone, K: Sharing> ops::IndexMut<usize> for Seq<T,K> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("Out of bounds access")
+    }
+}
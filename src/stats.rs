@@ -0,0 +1,74 @@
+use alloc::collections::BTreeSet;
+
+use lazy::{Thunk, Sharing, Peek};
+
+/// A report on the shape of a sequence's tree, from `Seq::stats`.
+///
+/// Nothing is forced to gather it, so it only covers the parts of the tree that have already been evaluated: an unevaluated thunk is counted, but whatever it would produce is not. Nodes shared between several places in the tree are counted once.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct SeqStats {
+    /// The number of evaluated, non-empty, levels of the spine.
+    pub depth: usize,
+    /// The number of nodes with two children.
+    pub node2: usize,
+    /// The number of nodes with three children.
+    pub node3: usize,
+    /// The number of leaves, each of which holds a chunk of elements.
+    pub leaves: usize,
+    /// `digits[i]` is the number of digits with `i + 1` nodes.
+    pub digits: [usize; 4],
+    /// The number of thunks holding a value.
+    pub evaluated: usize,
+    /// The number of thunks that have not been evaluated, including those being evaluated, and those whose evaluation panicked.
+    pub unevaluated: usize,
+    /// The number of thunks that have been evaluated to another thunk.
+    pub redirects: usize,
+    /// The number of thunks that are also part of the other sequence given to `Seq::stats_shared_with`. This is zero for `Seq::stats`.
+    pub shared: usize,
+}
+
+/// A walk over the evaluated part of a tree, collecting `SeqStats`.
+pub struct Walk {
+    pub stats: SeqStats,
+    seen: BTreeSet<usize>,
+}
+
+impl Walk {
+    pub fn new() -> Walk {
+        Walk {
+            stats: SeqStats::default(),
+            seen: BTreeSet::new(),
+        }
+    }
+
+    /// Count `thunk`, and any thunks it redirects to, and get its value if it has been evaluated and not seen before.
+    pub fn thunk<'a, T, K: Sharing>(&mut self, mut thunk: &'a Thunk<T, K>) -> Option<&'a T> {
+        loop {
+            if !self.seen.insert(thunk.addr()) {
+                return None
+            }
+            match thunk.peek() {
+                Peek::Evaluated(val) => {
+                    self.stats.evaluated += 1;
+                    return Some(val)
+                },
+                Peek::Redirect(target) => {
+                    self.stats.redirects += 1;
+                    thunk = target;
+                },
+                Peek::Unevaluated | Peek::Running | Peek::Poisoned => {
+                    self.stats.unevaluated += 1;
+                    return None
+                },
+            }
+        }
+    }
+
+    /// The stats, counting the thunks seen by both walks as shared.
+    pub fn shared_with(self, other: &Walk) -> SeqStats {
+        SeqStats {
+            shared: self.seen.intersection(&other.seen).count(),
+            .. self.stats
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Strategies for generating sequences with `proptest`.
+//!
+//! The sequences are built from a vector of elements, and a seed which chooses how: by collecting, by pushing onto either end, by appending smaller parts, and by splitting and re-joining. They shrink by shrinking the vector, which gives shorter sequences, and the seed.
+
+use proptest::arbitrary::{Arbitrary, any, any_with};
+use proptest::collection::{self, SizeRange, VecStrategy};
+use proptest::num;
+use proptest::strategy::{Map, Strategy};
+
+use arbitrary::{build, Choices, DEPTH};
+use lazy::Shared;
+use seq::Seq;
+
+/// The strategy returned by `seq`.
+pub type SeqStrategy<S> = Map<(VecStrategy<S>, num::u64::Any), fn((Vec<<S as Strategy>::Value>, u64)) -> Seq<<S as Strategy>::Value>>;
+
+/// A strategy for sequences of elements from `element`, with lengths in `size`, and varied shapes.
+///
+/// ```
+/// extern crate proptest;
+/// # extern crate immutable_seq;
+/// use proptest::prelude::*;
+/// use immutable_seq::strategy;
+///
+/// proptest! {
+///     fn split_then_append(seq in strategy::seq(any::<u32>(), 0..100), i in 0..100usize) {
+///         let (left, right) = seq.split(i);
+///         prop_assert_eq!(left.append(&right), seq);
+///     }
+/// }
+/// # fn main() { split_then_append(); }
+/// ```
+pub fn seq<S>(element: S, size: impl Into<SizeRange>) -> SeqStrategy<S>
+    where S: Strategy,
+          S::Value: Shared
+{
+    (collection::vec(element, size), any::<u64>()).prop_map(from_parts::<S::Value>)
+}
+
+fn from_parts<T: Shared>((elements, seed): (Vec<T>, u64)) -> Seq<T> {
+    let n = elements.len();
+    let mut choices = Choices::new(seed);
+    build(&mut elements.into_iter(), n, DEPTH, &mut |k| choices.choose(k))
+}
+
+/// Sequences with varied shapes; the parameters are the range of lengths, and the parameters for the elements.
+impl<T> Arbitrary for Seq<T>
+    where T: Arbitrary + Shared
+{
+    type Parameters = (SizeRange, T::Parameters);
+    type Strategy = SeqStrategy<T::Strategy>;
+
+    fn arbitrary_with((size, args): Self::Parameters) -> SeqStrategy<T::Strategy> {
+        seq(any_with::<T>(args), size)
+    }
+}
@@ -0,0 +1,623 @@
+//! Model-based testing of persistent sequences, with quickcheck.
+//!
+//! A test is a random series of actions on many versions of a sequence, which are kept in numbered slots. Each action reads some versions, and may add new versions to the end of the slots. The same actions are applied to a `Vec` model of each version, and the results, and the new versions, are compared with the model after every action. Since old versions stay in their slots, this checks that operations do not change the versions they are given, even when those share structure.
+//!
+//! `SeqAction` tests `Seq` itself. To test a wrapper, or another persistent sequence, implement `Subject` for it, and `Action` for a set of operations on it. Failing series are shrunk by dropping actions, along with the versions that depend on them, and by shrinking the arguments of actions.
+//!
+//! ```
+//! extern crate quickcheck;
+//! # extern crate immutable_seq;
+//! use immutable_seq::testing::{ActionSeries, SeqAction};
+//!
+//! fn seq_matches_vec(series: ActionSeries<SeqAction<u32>>) -> Result<(), String> {
+//!     series.run()
+//! }
+//!
+//! # fn main() {
+//! quickcheck::quickcheck(seq_matches_vec as fn(ActionSeries<SeqAction<u32>>) -> Result<(), String>);
+//! # }
+//! ```
+
+use core::fmt;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::string::String;
+
+use quickcheck::{self, Arbitrary, Gen};
+
+use lazy::Shared;
+use seq::Seq;
+
+/// The index of a version in a `Model`.
+#[derive(Clone,Copy,PartialEq,Eq,Hash,PartialOrd,Ord,Debug)]
+pub struct Slot(pub usize);
+
+/// A persistent sequence that can be tested against a `Vec`.
+pub trait Subject<T> {
+    /// Check that the sequence holds the `expected` elements, in order, and that its own invariants hold.
+    fn check(&self, expected: &[T]) -> Result<(), String>;
+}
+
+impl<T> Subject<T> for Seq<T>
+    where T: Shared + PartialEq + fmt::Debug
+{
+    fn check(&self, expected: &[T]) -> Result<(), String> {
+        self.check_invariants().map_err(|err| format!("{}", err))?;
+        if self.len() != expected.len() || !self.iter().eq(expected) {
+            return Err(format!("expected {:?}, got {:?}", expected, self))
+        }
+        Ok(())
+    }
+}
+
+/// An operation on the versions in a `Model`.
+///
+/// An action may only read versions from the slots it returns from `slots`, and must add `new_slots` versions to the model, so that series of actions can be shrunk.
+pub trait Action: Clone + fmt::Debug + Send + 'static {
+    /// The elements of the sequences.
+    type Item: Clone + PartialEq + fmt::Debug;
+    /// The sequences being tested.
+    type Subject: Subject<Self::Item> + Clone;
+
+    /// A random action, reading from slots below `slots`. If `slots` is zero, the action must not read any.
+    fn arbitrary<G: Gen>(g: &mut G, slots: usize) -> Self;
+
+    /// The slots the action reads.
+    fn slots(&mut self) -> Vec<&mut Slot>;
+
+    /// The number of versions the action adds to the model.
+    fn new_slots(&self) -> usize;
+
+    /// Simpler versions of the action, reading the same slots.
+    fn shrink(&self) -> Box<dyn Iterator<Item=Self>> {
+        quickcheck::empty_shrinker()
+    }
+
+    /// Apply the action to the model, and to the versions being tested, returning an error if they give different results.
+    fn apply(&self, model: &mut Model<Self::Item, Self::Subject>) -> Result<(), String>;
+}
+
+/// Versions of a sequence being tested, each with a `Vec` of the elements it should hold.
+pub struct Model<T, S> {
+    versions: Vec<(Vec<T>, S)>,
+}
+
+impl<T, S> Default for Model<T, S>
+    where T: Clone + PartialEq + fmt::Debug,
+          S: Subject<T> + Clone
+{
+    fn default() -> Model<T, S> {
+        Model::new()
+    }
+}
+
+impl<T, S> Model<T, S>
+    where T: Clone + PartialEq + fmt::Debug,
+          S: Subject<T> + Clone
+{
+    pub fn new() -> Model<T, S> {
+        Model {
+            versions: vec![],
+        }
+    }
+
+    /// The number of versions.
+    fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// Copies of the model and the sequence in `slot`.
+    pub fn get(&self, slot: Slot) -> (Vec<T>, S) {
+        self.versions[slot.0].clone()
+    }
+
+    /// Add a new version, which should hold `expected`.
+    pub fn push(&mut self, expected: Vec<T>, subject: S) {
+        self.versions.push((expected, subject));
+    }
+
+    /// Apply each action in turn, checking the versions each one adds.
+    pub fn apply_series<A>(&mut self, series: &ActionSeries<A>) -> Result<(), String>
+        where A: Action<Item=T, Subject=S>
+    {
+        for action in &series.actions {
+            let before = self.len();
+            action.apply(self).map_err(|err| format!("{}, in {:?}", err, action))?;
+            if self.len() != before + action.new_slots() {
+                return Err(format!("{:?} added {} versions, rather than {}", action, self.len() - before, action.new_slots()))
+            }
+            for (expected, subject) in &self.versions[before..] {
+                subject.check(expected).map_err(|err| format!("{}, after {:?}", err, action))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that `expected` and `actual` are equal, or give an error describing them.
+pub fn check_eq<U>(expected: U, actual: U) -> Result<(), String>
+    where U: PartialEq + fmt::Debug
+{
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, got {:?}", expected, actual))
+    }
+}
+
+/// A series of actions, which can be generated and shrunk by quickcheck.
+#[derive(Clone,Debug)]
+pub struct ActionSeries<A> {
+    actions: Vec<A>,
+    /// The number of slots after each action.
+    slots_after: Vec<usize>,
+}
+
+impl<A: Action> ActionSeries<A> {
+    /// A series of the given actions, which must only read slots added by earlier actions.
+    pub fn new(actions: Vec<A>) -> ActionSeries<A> {
+        let mut num_slots = 0;
+        let slots_after = actions.iter().map(|a| {
+            num_slots += a.new_slots();
+            num_slots
+        }).collect();
+        ActionSeries {
+            actions,
+            slots_after
+        }
+    }
+
+    pub fn actions(&self) -> &[A] {
+        &self.actions
+    }
+
+    /// Apply the actions to a new model.
+    pub fn run(&self) -> Result<(), String> {
+        Model::new().apply_series(self)
+    }
+
+    fn num_slots(&self) -> usize {
+        self.slots_after.last().cloned().unwrap_or(0)
+    }
+
+    fn extend_arbitrarily<G: Gen>(&mut self, g: &mut G) {
+        let action = A::arbitrary(g, self.num_slots());
+        let n = self.num_slots() + action.new_slots();
+        self.actions.push(action);
+        self.slots_after.push(n);
+    }
+
+    /// Drop the actions from `from` on that read `slot`, and renumber the later slots.
+    fn drop_slot(&mut self, from: usize, slot: Slot) {
+        let mut action_index = from;
+        while action_index < self.actions.len() {
+            let uses_slot = self.actions[action_index].slots().iter().any(|s| **s == slot);
+            if uses_slot {
+                self.drop_action(action_index);
+            } else {
+                action_index += 1;
+            }
+        }
+        action_index = from;
+        while action_index < self.actions.len() {
+            for s in self.actions[action_index].slots() {
+                if *s > slot {
+                    *s = Slot(s.0 - 1)
+                }
+            }
+            if self.slots_after[action_index] > slot.0 {
+                self.slots_after[action_index] -= 1;
+            }
+            action_index += 1;
+        }
+    }
+
+    /// Drop an action, and the actions that read the versions it added.
+    fn drop_action(&mut self, action_index: usize) {
+        let slots_before = if action_index > 0 {
+            self.slots_after[action_index-1]
+        } else {
+            0
+        };
+        let slots_after = self.slots_after[action_index];
+        let mut slot_index = slots_after;
+        while slot_index > slots_before {
+            slot_index -= 1;
+            self.drop_slot(action_index+1, Slot(slot_index));
+        }
+        self.actions.remove(action_index);
+        self.slots_after.remove(action_index);
+    }
+}
+
+/// Shrinks a series by dropping each action in turn, and then shrinking it.
+struct Shrinker<A> {
+    series: ActionSeries<A>,
+    action_index: usize,
+    action_shrinker: Box<dyn Iterator<Item=A>>,
+}
+
+impl<A: Action> Iterator for Shrinker<A> {
+    type Item = ActionSeries<A>;
+
+    fn next(&mut self) -> Option<ActionSeries<A>> {
+        if let Some(a) = self.action_shrinker.next() {
+            let mut s = self.series.clone();
+            s.actions[self.action_index] = a;
+            return Some(s)
+        }
+        let n = self.series.actions.len();
+        let i = &mut self.action_index;
+        *i += 1;
+        if *i < n {
+            self.action_shrinker = self.series.actions[*i].shrink();
+            let mut s = self.series.clone();
+            s.drop_action(*i);
+            Some(s)
+        } else {
+            None
+        }
+    }
+}
+
+impl<A: Action> Arbitrary for ActionSeries<A> {
+    fn arbitrary<G: Gen>(g: &mut G) -> ActionSeries<A> {
+        let mut series = ActionSeries::new(vec![]);
+        let size = { let s = g.size(); g.gen_range(0, s) };
+        for _ in 0..size {
+            series.extend_arbitrarily(g);
+        }
+        series
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=ActionSeries<A>>> {
+        Box::new(Shrinker {
+            series: self.clone(),
+            action_index: 0,
+            action_shrinker: quickcheck::empty_shrinker(),
+        })
+    }
+}
+
+/// The operations of `Seq`, and the corresponding operations on a `Vec`.
+#[derive(Clone,PartialEq,Eq,Hash,Debug)]
+pub enum SeqAction<T> {
+    Empty,
+    Singleton(T),
+    PushFront(Slot, T),
+    PushBack(Slot, T),
+    Append(Slot, Slot),
+    IsEmpty(Slot),
+    Len(Slot),
+    Front(Slot),
+    Back(Slot),
+    PopFront(Slot),
+    PopBack(Slot),
+    Update(Slot, usize, T),
+    Truncate(Slot, usize),
+    Skip(Slot, usize),
+    Split(Slot, usize),
+    Remove(Slot, usize),
+    Insert(Slot, usize, T),
+    Get(Slot, usize),
+    Iter(Slot),
+    FromVec(Vec<T>),
+    PushFrontMut(Slot, T),
+    PushBackMut(Slot, T),
+    PopFrontMut(Slot),
+    PopBackMut(Slot),
+    GetMut(Slot, usize, T),
+    Reverse(Slot),
+}
+
+use self::SeqAction::{
+    Empty,
+    Singleton,
+    PushFront,
+    PushBack,
+    Append,
+    IsEmpty,
+    Len,
+    Front,
+    Back,
+    PopFront,
+    PopBack,
+    Update,
+    Truncate,
+    Skip,
+    Split,
+    Remove,
+    Insert,
+    Get,
+    Iter,
+    FromVec,
+    PushFrontMut,
+    PushBackMut,
+    PopFrontMut,
+    PopBackMut,
+    GetMut,
+    Reverse,
+};
+
+impl<T> Action for SeqAction<T>
+    where T: Arbitrary + PartialEq + fmt::Debug + Shared
+{
+    type Item = T;
+    type Subject = Seq<T>;
+
+    fn arbitrary<G: Gen>(g: &mut G, slots: usize) -> SeqAction<T> {
+        if slots == 0 {
+            return match g.next_u32() % 3 {
+                0 => Empty,
+                1 => Singleton(T::arbitrary(g)),
+                _ => FromVec(Vec::<T>::arbitrary(g)),
+            }
+        }
+        let slot = |g: &mut G| Slot(g.next_u32() as usize % slots);
+        match g.next_u32() % 26 {
+            0  => Empty,
+            1  => Singleton    (T::arbitrary(g)),
+            2  => PushFront    (slot(g), T::arbitrary(g)),
+            3  => PushBack     (slot(g), T::arbitrary(g)),
+            4  => Append       (slot(g), slot(g)),
+            5  => IsEmpty      (slot(g)),
+            6  => Len          (slot(g)),
+            7  => Front        (slot(g)),
+            8  => Back         (slot(g)),
+            9  => PopFront     (slot(g)),
+            10 => PopBack      (slot(g)),
+            11 => Update       (slot(g), usize::arbitrary(g), T::arbitrary(g)),
+            12 => Truncate     (slot(g), usize::arbitrary(g)),
+            13 => Skip         (slot(g), usize::arbitrary(g)),
+            14 => Split        (slot(g), usize::arbitrary(g)),
+            15 => Remove       (slot(g), usize::arbitrary(g)),
+            16 => Insert       (slot(g), usize::arbitrary(g), T::arbitrary(g)),
+            17 => Get          (slot(g), usize::arbitrary(g)),
+            18 => Iter         (slot(g)),
+            19 => FromVec      (Vec::<T>::arbitrary(g)),
+            20 => PushFrontMut (slot(g), T::arbitrary(g)),
+            21 => PushBackMut  (slot(g), T::arbitrary(g)),
+            22 => PopFrontMut  (slot(g)),
+            23 => PopBackMut   (slot(g)),
+            24 => GetMut       (slot(g), usize::arbitrary(g), T::arbitrary(g)),
+            _  => Reverse      (slot(g)),
+        }
+    }
+
+    fn slots(&mut self) -> Vec<&mut Slot> {
+        match *self {
+            Append(ref mut s1, ref mut s2) => vec![s1, s2],
+            PushFront(ref mut s, _) | PushBack(ref mut s, _) |
+            IsEmpty(ref mut s) | Len(ref mut s) | Front(ref mut s) | Back(ref mut s) |
+            PopFront(ref mut s) | PopBack(ref mut s) |
+            Update(ref mut s, _, _) | Truncate(ref mut s, _) | Skip(ref mut s, _) |
+            Split(ref mut s, _) | Remove(ref mut s, _) | Insert(ref mut s, _, _) |
+            Get(ref mut s, _) | Iter(ref mut s) |
+            PushFrontMut(ref mut s, _) | PushBackMut(ref mut s, _) |
+            PopFrontMut(ref mut s) | PopBackMut(ref mut s) | GetMut(ref mut s, _, _) |
+            Reverse(ref mut s) => vec![s],
+            Empty | Singleton(_) | FromVec(_) => vec![],
+        }
+    }
+
+    fn new_slots(&self) -> usize {
+        match *self {
+            Split(_, _) => 2,
+            IsEmpty(_) | Len(_) | Front(_) | Back(_) | Get(_, _) | Iter(_) => 0,
+            _ => 1,
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item=SeqAction<T>>> {
+        match *self {
+            Split(s, n) =>
+                Box::new(n.shrink().map(move |i| Split(s, i))),
+            Singleton(ref x) =>
+                Box::new(x.shrink().map(Singleton)),
+            PushFront(s, ref x) =>
+                Box::new(x.shrink().map(move |y| PushFront(s, y))),
+            PushBack(s, ref x) =>
+                Box::new(x.shrink().map(move |y| PushBack(s, y))),
+            Update(s, n, ref x) =>
+                Box::new((n, x.clone()).shrink().map(move |(i, y)| Update(s, i, y))),
+            Truncate(s, n) =>
+                Box::new(n.shrink().map(move |i| Truncate(s, i))),
+            Skip(s, n) =>
+                Box::new(n.shrink().map(move |i| Skip(s, i))),
+            Remove(s, n) =>
+                Box::new(n.shrink().map(move |i| Remove(s, i))),
+            Insert(s, n, ref x) =>
+                Box::new((n, x.clone()).shrink().map(move |(i, y)| Insert(s, i, y))),
+            FromVec(ref v) =>
+                Box::new(v.shrink().map(FromVec)),
+            Get(s, n) =>
+                Box::new(n.shrink().map(move |i| Get(s, i))),
+            PushFrontMut(s, ref x) =>
+                Box::new(x.shrink().map(move |y| PushFrontMut(s, y))),
+            PushBackMut(s, ref x) =>
+                Box::new(x.shrink().map(move |y| PushBackMut(s, y))),
+            GetMut(s, n, ref x) =>
+                Box::new((n, x.clone()).shrink().map(move |(i, y)| GetMut(s, i, y))),
+            _ => quickcheck::empty_shrinker(),
+        }
+    }
+
+    fn apply(&self, model: &mut Model<T, Seq<T>>) -> Result<(), String> {
+        match *self {
+            Empty =>
+                model.push(vec![], Seq::empty()),
+            Singleton(ref x) =>
+                model.push(vec![x.clone()], Seq::singleton(x.clone())),
+            PushFront(slot, ref x) => {
+                let (mut vec, seq) = model.get(slot);
+                vec.insert(0, x.clone());
+                model.push(vec, seq.push_front(x.clone()));
+            },
+            PushBack(slot, ref x) => {
+                let (mut vec, seq) = model.get(slot);
+                vec.push(x.clone());
+                model.push(vec, seq.push_back(x.clone()));
+            },
+            Append(slot1, slot2) => {
+                let (mut vec1, seq1) = model.get(slot1);
+                let (mut vec2, seq2) = model.get(slot2);
+                vec1.append(&mut vec2);
+                model.push(vec1, seq1.append(&seq2));
+            },
+            IsEmpty(slot) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec.is_empty(), seq.is_empty())?;
+            },
+            Len(slot) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec.len(), seq.len())?;
+            },
+            Front(slot) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec.first(), seq.front())?;
+            },
+            Back(slot) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec.last(), seq.back())?;
+            },
+            PopFront(slot) => {
+                let (mut vec, seq) = model.get(slot);
+                if !vec.is_empty() {
+                    vec.remove(0);
+                }
+                model.push(vec, seq.pop_front());
+            },
+            PopBack(slot) => {
+                let (mut vec, seq) = model.get(slot);
+                vec.pop();
+                model.push(vec, seq.pop_back());
+            },
+            Update(slot, index, ref x) => {
+                let (mut vec, seq) = model.get(slot);
+                if let Some(v) = vec.get_mut(index) {
+                    *v = x.clone();
+                }
+                model.push(vec, seq.update(index, x.clone()));
+            },
+            Truncate(slot, length) => {
+                let (mut vec, seq) = model.get(slot);
+                vec.truncate(length);
+                model.push(vec, seq.truncate(length));
+            },
+            Skip(slot, length) => {
+                let (vec, seq) = model.get(slot);
+                let vec = vec.into_iter().skip(length).collect();
+                model.push(vec, seq.skip(length));
+            },
+            Split(slot, index) => {
+                let (mut vec1, seq) = model.get(slot);
+                let vec2 = if index <= vec1.len() {
+                    vec1.split_off(index)
+                } else {
+                    vec![]
+                };
+                let (seq1, seq2) = seq.split(index);
+                model.push(vec1, seq1);
+                model.push(vec2, seq2);
+            },
+            Remove(slot, index) => {
+                let (mut vec, seq) = model.get(slot);
+                if index < vec.len() {
+                    vec.remove(index);
+                }
+                model.push(vec, seq.remove(index));
+            },
+            Insert(slot, index, ref x) => {
+                let (mut vec, seq) = model.get(slot);
+                if index >= vec.len() {
+                    vec.push(x.clone())
+                } else {
+                    vec.insert(index, x.clone());
+                }
+                model.push(vec, seq.insert(index, x.clone()));
+            },
+            Get(slot, index) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec.get(index), seq.get(index))?;
+            },
+            Iter(slot) => {
+                let (vec, seq) = model.get(slot);
+                check_eq(vec, seq.iter().cloned().collect::<Vec<T>>())?;
+            },
+            FromVec(ref vec) =>
+                model.push(vec.clone(), vec.iter().cloned().collect()),
+            PushFrontMut(slot, ref x) => {
+                let (mut vec, mut seq) = model.get(slot);
+                vec.insert(0, x.clone());
+                seq.push_front_mut(x.clone());
+                model.push(vec, seq);
+            },
+            PushBackMut(slot, ref x) => {
+                let (mut vec, mut seq) = model.get(slot);
+                vec.push(x.clone());
+                seq.push_back_mut(x.clone());
+                model.push(vec, seq);
+            },
+            PopFrontMut(slot) => {
+                let (mut vec, mut seq) = model.get(slot);
+                let expected = if !vec.is_empty() {
+                    Some(vec.remove(0))
+                } else {
+                    None
+                };
+                check_eq(expected, seq.pop_front_mut())?;
+                model.push(vec, seq);
+            },
+            PopBackMut(slot) => {
+                let (mut vec, mut seq) = model.get(slot);
+                check_eq(vec.pop(), seq.pop_back_mut())?;
+                model.push(vec, seq);
+            },
+            GetMut(slot, index, ref x) => {
+                let (mut vec, mut seq) = model.get(slot);
+                check_eq(vec.get_mut(index).is_some(), seq.get_mut(index).is_some())?;
+                if let Some(v) = vec.get_mut(index) {
+                    *v = x.clone();
+                }
+                if let Some(v) = seq.get_mut(index) {
+                    *v = x.clone();
+                }
+                model.push(vec, seq);
+            },
+            Reverse(slot) => {
+                let (mut vec, seq) = model.get(slot);
+                vec.reverse();
+                model.push(vec, seq.reverse());
+            },
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ActionSeries, Model, SeqAction, Slot};
+    use super::SeqAction::*;
+
+    #[test]
+    fn test_action_series_drop_slot() {
+        let mut series = ActionSeries::<SeqAction<u32>>::new(vec![Empty, Insert(Slot(0), 22, 59), Append(Slot(0),Slot(1)), Split(Slot(1),39), Back(Slot(3))]);
+        series.drop_action(1);
+        assert_eq!(series.actions, vec![Empty]);
+        assert_eq!(series.slots_after, vec![1]);
+    }
+
+    #[test]
+    fn test_insert_past_end_of_emptied_seq() {
+        let series = ActionSeries::<SeqAction<u32>>::new(
+            vec![Singleton(48), Singleton(0), PopBack(Slot(1)), Insert(Slot(2), 1, 0), Back(Slot(3))]
+        );
+        assert_eq!(Model::new().apply_series(&series), Ok(()));
+    }
+
+    quickcheck! {
+        fn check_model_u32(series: ActionSeries<SeqAction<u32>>) -> Result<(),String> {
+            series.run()
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Timings comparing the default (lazy) build with the `strict` feature.
+//!
+//! These are ignored by default; run them in both modes and compare the output:
+//!
+//! ```text
+//! cargo test --release --test bench -- --ignored --nocapture --test-threads 1
+//! cargo test --release --test bench --features strict -- --ignored --nocapture --test-threads 1
+//! ```
+//!
+//! Each benchmark reports the total time, and the slowest single operation, which shows the latency spikes caused by forcing deferred work.
+//!
+//! The `versions` benchmarks keep every version of a sequence, and then drop them all, once with the nodes allocated from the global allocator, and once from an `Arena`.
+
+extern crate immutable_seq;
+
+use std::time::{Duration, Instant};
+
+use immutable_seq::Seq;
+#[cfg(feature = "std")]
+use immutable_seq::heap::{Arena, NodeHeap};
+
+const N: usize = 200000;
+
+fn mode() -> &'static str {
+    if cfg!(feature = "strict") { "strict" } else { "lazy" }
+}
+
+/// Run `op` `n` times, and print the total and maximum time taken.
+fn time<F>(name: &str, n: usize, mut op: F)
+    where F: FnMut(usize)
+{
+    let mut max = Duration::new(0, 0);
+    let start = Instant::now();
+    for i in 0..n {
+        let op_start = Instant::now();
+        op(i);
+        let elapsed = op_start.elapsed();
+        if elapsed > max {
+            max = elapsed;
+        }
+    }
+    let total = start.elapsed();
+    println!("{:>6} {:<32} total: {:>10.3?}  per op: {:>10.3?}  max op: {:>10.3?}",
+             mode(), name, total, total / n as u32, max);
+}
+
+/// Drop `val`, and print the time taken.
+fn time_drop<T>(name: &str, val: T) {
+    let start = Instant::now();
+    drop(val);
+    println!("{:>6} {:<32} total: {:>10.3?}", mode(), name, start.elapsed());
+}
+
+/// Push `N` elements onto the back, one at a time, keeping every version, then split and re-join each of a tenth of them.
+fn versions(name: &str) {
+    let mut seq = Seq::empty();
+    let mut versions = Vec::with_capacity(N);
+    time(&format!("{} push_back", name), N, |i| {
+        seq = seq.push_back(i);
+        versions.push(seq.clone());
+    });
+    time(&format!("{} split + append", name), N / 10, |i| {
+        let (before, after) = versions[i * 10].split(i * 5);
+        versions[i * 10] = after.append(&before);
+    });
+    time(&format!("{} iter", name), 10, |i| {
+        assert_eq!(versions[N - 1 - i].iter().count(), N - i)
+    });
+    time_drop(&format!("{} drop", name), (seq, versions));
+}
+
+#[test]
+#[ignore]
+fn bench_versions() {
+    versions("versions");
+}
+
+#[test]
+#[ignore]
+#[cfg(feature = "std")]
+fn bench_versions_arena() {
+    let heap = NodeHeap::new(Arena::new());
+    heap.scope(|| versions("versions, arena"));
+}
+
+#[test]
+#[ignore]
+fn bench_push_back() {
+    let mut seq = Seq::empty();
+    time("push_back", N, |i| seq = seq.push_back(i));
+    assert_eq!(seq.len(), N);
+}
+
+#[test]
+#[ignore]
+fn bench_push_front() {
+    let mut seq = Seq::empty();
+    time("push_front", N, |i| seq = seq.push_front(i));
+    assert_eq!(seq.len(), N);
+}
+
+#[test]
+#[ignore]
+fn bench_push_back_mut() {
+    let mut seq = Seq::empty();
+    time("push_back_mut", N, |i| seq.push_back_mut(i));
+    assert_eq!(seq.len(), N);
+}
+
+#[test]
+#[ignore]
+fn bench_pop_front() {
+    let mut seq: Seq<usize> = (0..N).collect();
+    time("pop_front", N, |_| seq = seq.pop_front());
+    assert!(seq.is_empty());
+}
+
+#[test]
+#[ignore]
+fn bench_deque() {
+    let mut seq: Seq<usize> = (0..1000).collect();
+    time("push_back + pop_front", N, |i| seq = seq.push_back(i).pop_front());
+    assert_eq!(seq.len(), 1000);
+}
+
+#[test]
+#[ignore]
+fn bench_get() {
+    let seq: Seq<usize> = (0..N).collect();
+    time("get", N, |i| assert_eq!(seq.get((i * 7919) % N), Some(&((i * 7919) % N))));
+}
+
+#[test]
+#[ignore]
+fn bench_update() {
+    let mut seq: Seq<usize> = (0..N).collect();
+    time("update", N / 10, |i| seq = seq.update((i * 7919) % N, i));
+    assert_eq!(seq.len(), N);
+}
+
+#[test]
+#[ignore]
+fn bench_get_many() {
+    let seq: Seq<usize> = (0..N).collect();
+    let mut indices: Vec<usize> = (0..1000).map(|i| (i * 7919) % N).collect();
+    indices.sort();
+    time("get 1000 one by one", 100, |_| for &i in &indices {
+        assert_eq!(seq.get(i), Some(&i));
+    });
+    time("get_many 1000", 100, |_| assert_eq!(seq.get_many(&indices).len(), indices.len()));
+}
+
+#[test]
+#[ignore]
+fn bench_update_many() {
+    let seq: Seq<usize> = (0..N).collect();
+    let mut indices: Vec<usize> = (0..1000).map(|i| (i * 7919) % N).collect();
+    indices.sort();
+    time("update 1000 one by one", 100, |_| {
+        let updated = indices.iter().fold(seq.clone(), |seq, &i| seq.update(i, 0));
+        assert_eq!(updated.len(), N);
+    });
+    time("update_many 1000", 100, |_| {
+        let updated = seq.update_many(indices.iter().map(|&i| (i, 0)));
+        assert_eq!(updated.len(), N);
+    });
+}
+
+#[test]
+#[ignore]
+fn bench_filter() {
+    let seq: Seq<usize> = (0..N).collect();
+    time("filter half by collect", 10, |_| assert_eq!(seq.iter().filter(|&x| x % 2 == 0).cloned().collect::<Seq<_>>().len(), N / 2));
+    time("filter half", 10, |_| assert_eq!(seq.filter(|x| x % 2 == 0).len(), N / 2));
+    time("filter one by collect", 10, |_| assert_eq!(seq.iter().filter(|&&x| x != N / 2).cloned().collect::<Seq<_>>().len(), N - 1));
+    time("filter one", 10, |_| assert_eq!(seq.filter(|&x| x != N / 2).len(), N - 1));
+}
+
+#[test]
+#[ignore]
+fn bench_split_append() {
+    let mut seq: Seq<usize> = (0..N).collect();
+    time("split + append", N / 10, |i| {
+        let (before, after) = seq.split((i * 7919) % N);
+        seq = after.append(&before);
+    });
+    assert_eq!(seq.len(), N);
+}
+
+#[test]
+#[ignore]
+fn bench_iter() {
+    let seqs: Vec<Seq<usize>> = (0..10).map(|_| (0..N).collect()).collect();
+    time("iter", seqs.len(), |i| assert_eq!(seqs[i].iter().count(), N));
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "rayon")]
+
+extern crate immutable_seq;
+extern crate rayon;
+
+use rayon::prelude::*;
+
+use immutable_seq::SyncSeq;
+
+#[test]
+fn test_par_iter_empty() {
+    let seq: SyncSeq<u32> = SyncSeq::empty_sync();
+    let result: Vec<u32> = seq.par_iter().cloned().collect();
+    assert_eq!(result, vec![]);
+}
+
+#[test]
+fn test_par_iter() {
+    let n = 10000;
+    let seq: SyncSeq<usize> = (0..n).collect();
+    let result: Vec<usize> = seq.par_iter().cloned().collect();
+    let expected: Vec<usize> = (0..n).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_par_iter_appended() {
+    let mut seq: SyncSeq<usize> = SyncSeq::empty_sync();
+    for i in 0..100 {
+        let chunk: SyncSeq<usize> = (i * 100..(i + 1) * 100).collect();
+        seq = seq.append(&chunk);
+    }
+    let result: Vec<usize> = seq.par_iter().cloned().collect();
+    let expected: Vec<usize> = (0..10000).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_par_iter_indexed() {
+    let n = 1000;
+    let seq: SyncSeq<usize> = (0..n).collect();
+    assert_eq!(seq.par_iter().len(), n);
+    assert!(seq.par_iter().enumerate().all(|(i, &x)| i == x));
+    let result: Vec<usize> = seq.par_iter().rev().cloned().collect();
+    let expected: Vec<usize> = (0..n).rev().collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_par_iter_min_len() {
+    let n = 1000;
+    let seq: SyncSeq<usize> = (0..n).collect();
+    for len in 1..10 {
+        let result: Vec<usize> = seq.par_iter().with_max_len(len).cloned().collect();
+        let expected: Vec<usize> = (0..n).collect();
+        assert_eq!(result, expected);
+    }
+}
+
+#[test]
+fn test_par_map() {
+    let n = 10000;
+    let seq: SyncSeq<usize> = (0..n).collect();
+    let result = seq.par_map(|x| x * 2);
+    let expected: SyncSeq<usize> = (0..n).map(|x| x * 2).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_par_fold() {
+    let n = 10000;
+    let seq: SyncSeq<usize> = (0..n).collect();
+    let sum = seq.par_fold(|| 0, |acc, x| acc + x, |a, b| a + b);
+    assert_eq!(sum, n * (n - 1) / 2);
+    let digits = seq.truncate(12).par_fold(String::new, |acc, x| acc + &x.to_string(), |a, b| a + &b);
+    assert_eq!(digits, "01234567891011");
+}
+
+#[test]
+fn test_collect_seq() {
+    let n = 10000;
+    let seq: SyncSeq<usize> = (0..n).into_par_iter().collect();
+    let expected: SyncSeq<usize> = (0..n).collect();
+    assert_eq!(seq, expected);
+}
+
+#[test]
+fn test_par_iter_split_everywhere() {
+    for n in 0..100 {
+        let seq: SyncSeq<usize> = (0..n).fold(SyncSeq::empty_sync(), |seq, i| {
+            if i % 3 == 0 { seq.push_front(n - i) } else { seq.push_back(n - i) }
+        });
+        let result: Vec<usize> = seq.par_iter().with_max_len(1).cloned().collect();
+        let expected: Vec<usize> = seq.iter().cloned().collect();
+        assert_eq!(result, expected);
+    }
+}
@@ -1,11 +1,21 @@
 #[macro_use]
 extern crate immutable_seq;
 
-use immutable_seq::Seq;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Arc;
+#[cfg(feature = "sync")]
+use std::thread;
+#[cfg(not(feature = "strict"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use immutable_seq::{Seq, SeqStats};
+#[cfg(feature = "sync")]
+use immutable_seq::SyncSeq;
 
 #[test]
 fn test_iter_empty() {
-    let result: Vec<u32> = Seq::empty().iter().map(|x| *x).collect();
+    let result: Vec<u32> = Seq::empty().iter().copied().collect();
     let expected: Vec<u32> = vec![];
     assert_eq!(result, expected);
 }
@@ -22,7 +32,7 @@ fn test_empty_len() {
 
 #[test]
 fn test_iter_singleton() {
-    let result: Vec<u32> = Seq::singleton(42).iter().map(|x| *x).collect();
+    let result: Vec<u32> = Seq::singleton(42).iter().copied().collect();
     let expected: Vec<u32> = vec![42];
     assert_eq!(result, expected);
 }
@@ -43,8 +53,8 @@ fn test_iter_push_front() {
     for i in 0..20 {
         seq = seq.push_front(i);
     }
-    let result: Vec<u32> = seq.iter().map(|x| *x).collect();
-    let mut expected: Vec<u32> = (0..20).into_iter().collect();
+    let result: Vec<u32> = seq.iter().copied().collect();
+    let mut expected: Vec<u32> = (0..20).collect();
     expected.reverse();
     assert_eq!(result, expected);
 }
@@ -55,15 +65,15 @@ fn test_iter_push_back() {
     for i in 0..20 {
         seq = seq.push_back(i);
     }
-    let result: Vec<u32> = seq.iter().map(|x| *x).collect();
-    let expected: Vec<u32> = (0..20).into_iter().collect();
+    let result: Vec<u32> = seq.iter().copied().collect();
+    let expected: Vec<u32> = (0..20).collect();
     assert_eq!(result, expected);
 }
 
 #[test]
 fn test_vec_macro() {
     let seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
-    let result: Vec<u32> = seq.iter().map(|x| *x).collect();
+    let result: Vec<u32> = seq.iter().copied().collect();
     let expected: Vec<u32> = vec![0,1,2,3,4,5,6,7,8,9];
     assert_eq!(result, expected);
 }
@@ -77,8 +87,8 @@ fn test_iter_append() {
         ys = ys.push_back(20 + i);
     }
     let seq = xs.append(&ys);
-    let result: Vec<u32> = seq.iter().map(|x| *x).collect();
-    let expected: Vec<u32> = (0..40).into_iter().collect();
+    let result: Vec<u32> = seq.iter().copied().collect();
+    let expected: Vec<u32> = (0..40).collect();
     assert_eq!(result, expected);
 }
 
@@ -90,19 +100,15 @@ fn test_front_empty() {
 #[test]
 fn test_front_nonempty() {
     let seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
-    assert_eq!(seq.front().map(|x| *x),Some(0));
+    assert_eq!(seq.front().copied(),Some(0));
 }
 
 #[test]
 fn test_front_pop_front() {
     let mut seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
     let mut vec: Vec<u32> = vec![];
-    loop {
-        if let Some(front) = seq.front() {
-            vec.push(*front);
-        } else {
-            break;
-        }
+    while let Some(front) = seq.front() {
+        vec.push(*front);
         seq = seq.pop_front();
     }
     assert_eq!(vec,vec![0,1,2,3,4,5,6,7,8,9]);
@@ -116,19 +122,15 @@ fn test_back_empty() {
 #[test]
 fn test_back_nonempty() {
     let seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
-    assert_eq!(seq.back().map(|x| *x),Some(9));
+    assert_eq!(seq.back().copied(),Some(9));
 }
 
 #[test]
 fn test_back_pop_back() {
     let mut seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
     let mut vec: Vec<u32> = vec![];
-    loop {
-        if let Some(back) = seq.back() {
-            vec.push(*back);
-        } else {
-            break;
-        }
+    while let Some(back) = seq.back() {
+        vec.push(*back);
         seq = seq.pop_back();
     }
     assert_eq!(vec,vec![9,8,7,6,5,4,3,2,1,0]);
@@ -167,7 +169,7 @@ fn test_index() {
 #[should_panic]
 fn test_index_out_of_bounds() {
     let seq: Seq<u32> = seq![0,1,2,3,4,5,6,7,8,9];
-    seq[10];
+    let _ = seq[10];
 }
 
 #[test]
@@ -191,13 +193,13 @@ fn test_split_singleton() {
 #[test]
 fn test_split() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
         let (before, after) = seq.split(i);
-        let before:Vec<usize> = before.iter().map(|x| *x).collect();
-        let before_expected:Vec<usize> = (0..i).into_iter().collect();
-        let after:Vec<usize> = after.iter().map(|x| *x).collect();
-        let after_expected:Vec<usize> = (i..n).into_iter().collect();
+        let before:Vec<usize> = before.iter().copied().collect();
+        let before_expected:Vec<usize> = (0..i).collect();
+        let after:Vec<usize> = after.iter().copied().collect();
+        let after_expected:Vec<usize> = (i..n).collect();
         assert_eq!(before, before_expected);
         assert_eq!(after, after_expected);
     }
@@ -206,10 +208,10 @@ fn test_split() {
 #[test]
 fn test_truncate() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
-        let res:Vec<usize> = seq.truncate(i).iter().map(|x| *x).collect();
-        let expected:Vec<usize> = (0..i).into_iter().collect();
+        let res:Vec<usize> = seq.truncate(i).iter().copied().collect();
+        let expected:Vec<usize> = (0..i).collect();
         assert_eq!(res, expected);
     }
 }
@@ -217,10 +219,10 @@ fn test_truncate() {
 #[test]
 fn test_skip() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
-        let res:Vec<usize> = seq.skip(i).iter().map(|x| *x).collect();
-        let expected:Vec<usize> = (i..n).into_iter().collect();
+        let res:Vec<usize> = seq.skip(i).iter().copied().collect();
+        let expected:Vec<usize> = (i..n).collect();
         assert_eq!(res, expected);
     }
 }
@@ -228,10 +230,10 @@ fn test_skip() {
 #[test]
 fn test_remove() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
-        let res:Vec<usize> = seq.remove(i).iter().map(|x| *x).collect();
-        let expected:Vec<usize> = (0..n).into_iter().filter(|&j| {j != i}).collect();
+        let res:Vec<usize> = seq.remove(i).iter().copied().collect();
+        let expected:Vec<usize> = (0..n).filter(|&j| {j != i}).collect();
         assert_eq!(res, expected);
     }
 }
@@ -239,10 +241,10 @@ fn test_remove() {
 #[test]
 fn test_insert() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
-        let res:Vec<usize> = seq.insert(i,n).iter().map(|x| *x).collect();
-        let mut expected:Vec<usize> = (0..n).into_iter().collect();
+        let res:Vec<usize> = seq.insert(i,n).iter().copied().collect();
+        let mut expected:Vec<usize> = (0..n).collect();
         expected.insert(i,n);
         assert_eq!(res, expected);
     }
@@ -251,11 +253,11 @@ fn test_insert() {
 #[test]
 fn test_adjust() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
         println!("{}",i);
-        let res:Vec<usize> = seq.adjust(i,|x| x+1).iter().map(|x| *x).collect();
-        let mut expected:Vec<usize> = (0..n).into_iter().collect();
+        let res:Vec<usize> = seq.adjust(i,|x| x+1).iter().copied().collect();
+        let mut expected:Vec<usize> = (0..n).collect();
         expected[i] += 1;
         assert_eq!(res, expected);
     }
@@ -264,13 +266,1104 @@ fn test_adjust() {
 #[test]
 fn test_update() {
     let n = 10;
-    let seq: Seq<usize> = (0..n).into_iter().collect();
+    let seq: Seq<usize> = (0..n).collect();
     for i in 0..n {
         println!("{}",i);
-        let res:Vec<usize> = seq.update(i,n).iter().map(|x| *x).collect();
-        let mut expected:Vec<usize> = (0..n).into_iter().collect();
+        let res:Vec<usize> = seq.update(i,n).iter().copied().collect();
+        let mut expected:Vec<usize> = (0..n).collect();
         expected[i] = n;
         assert_eq!(res, expected);
     }
 }
 
+
+#[test]
+fn test_push_back_mut() {
+    let mut seq: Seq<u32> = Seq::empty();
+    for i in 0..100 {
+        seq.push_back_mut(i);
+    }
+    let result: Vec<u32> = seq.iter().cloned().collect();
+    let expected: Vec<u32> = (0..100).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_push_front_mut() {
+    let mut seq: Seq<u32> = Seq::empty();
+    for i in 0..100 {
+        seq.push_front_mut(i);
+    }
+    let result: Vec<u32> = seq.iter().cloned().collect();
+    let expected: Vec<u32> = (0..100).rev().collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_pop_front_mut() {
+    let mut seq: Seq<u32> = (0..100).collect();
+    let mut result = vec![];
+    while let Some(x) = seq.pop_front_mut() {
+        result.push(x);
+    }
+    let expected: Vec<u32> = (0..100).collect();
+    assert_eq!(result, expected);
+    assert!(seq.is_empty());
+}
+
+#[test]
+fn test_pop_back_mut() {
+    let mut seq: Seq<u32> = (0..100).collect();
+    let mut result = vec![];
+    while let Some(x) = seq.pop_back_mut() {
+        result.push(x);
+    }
+    let expected: Vec<u32> = (0..100).rev().collect();
+    assert_eq!(result, expected);
+    assert!(seq.is_empty());
+}
+
+#[test]
+fn test_get_mut() {
+    let n = 50;
+    let mut seq: Seq<usize> = (0..n).collect();
+    for i in 0..n {
+        *seq.get_mut(i).unwrap() *= 2;
+    }
+    assert_eq!(seq.get_mut(n), None);
+    let result: Vec<usize> = seq.iter().cloned().collect();
+    let expected: Vec<usize> = (0..n).map(|x| x * 2).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_mut_preserves_shared() {
+    let n = 50;
+    let original: Seq<usize> = (0..n).collect();
+    let mut seq = original.clone();
+    for i in 0..n {
+        *seq.get_mut(i).unwrap() += 1;
+    }
+    seq.push_back_mut(n);
+    seq.push_front_mut(n);
+    assert_eq!(seq.pop_back_mut(), Some(n));
+    assert_eq!(seq.pop_front_mut(), Some(n));
+    assert_eq!(seq.pop_front_mut(), Some(1));
+    let expected: Seq<usize> = (0..n).collect();
+    assert_eq!(original, expected);
+    let expected: Seq<usize> = (2..n+1).collect();
+    assert_eq!(seq, expected);
+}
+
+#[test]
+fn test_iter_rev() {
+    let n = 100;
+    let seq: Seq<usize> = (0..n).collect();
+    let result: Vec<usize> = seq.iter().rev().cloned().collect();
+    let expected: Vec<usize> = (0..n).rev().collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_iter_both_ends() {
+    let n = 100;
+    let seq: Seq<usize> = (0..n).collect();
+    let mut iter = seq.iter();
+    for i in 0..n/2 {
+        assert_eq!(iter.len(), n - 2 * i);
+        assert_eq!(iter.next(), Some(&i));
+        assert_eq!(iter.next_back(), Some(&(n - 1 - i)));
+    }
+    assert_eq!(iter.len(), 0);
+    assert_eq!(iter.next(), None);
+    assert_eq!(iter.next_back(), None);
+}
+
+#[test]
+fn test_seq_of_rc() {
+    let shared = Rc::new(7u32);
+    let seq: Seq<Rc<u32>> = (0..100).map(|_| shared.clone()).collect();
+    let (left, right) = seq.split(50);
+    assert_eq!(left.append(&right), seq);
+    assert_eq!(Rc::strong_count(&shared), 101);
+    drop((seq, left, right));
+    assert_eq!(Rc::strong_count(&shared), 1);
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn test_sync_seq_across_threads() {
+    let seq: SyncSeq<usize> = (0..1000).collect();
+    let handles: Vec<_> = (0..4).map(|i| {
+        let seq = seq.clone();
+        thread::spawn(move || seq.iter().skip(i * 250).take(250).sum::<usize>())
+    }).collect();
+    let total: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    assert_eq!(total, 999 * 1000 / 2);
+    assert_eq!(SyncSeq::repeat_sync(1, 100).len(), 100);
+    assert_eq!(seq.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_map() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let mapped = seq.append(&seq).map(|x| x.to_string());
+    let result: Vec<String> = mapped.iter().cloned().collect();
+    let expected: Vec<String> = (0..n).chain(0..n).map(|x| x.to_string()).collect();
+    assert_eq!(result, expected);
+    assert_eq!(mapped.len(), 2 * n);
+}
+
+#[test]
+fn test_map_in_order() {
+    let seq: Seq<usize> = (0..100).collect();
+    let mut count = 0;
+    let mapped = seq.map(|_| {
+        count += 1;
+        count
+    });
+    let result: Vec<usize> = mapped.iter().cloned().collect();
+    let expected: Vec<usize> = (1..101).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_lazy_map() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let mapped = seq.append(&seq).lazy_map(|x| x * 2);
+    let result: Vec<usize> = mapped.iter().cloned().collect();
+    let expected: Vec<usize> = (0..n).chain(0..n).map(|x| x * 2).collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_lazy_map_is_lazy() {
+    let n = 100000;
+    let seq: Seq<usize> = (0..n).collect();
+    let count = Arc::new(AtomicUsize::new(0));
+    let mapped = {
+        let count = count.clone();
+        seq.lazy_map(move |x| {
+            count.fetch_add(1, Ordering::SeqCst);
+            x + 1
+        })
+    };
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(mapped.len(), n);
+    assert_eq!(mapped.get(n / 2), Some(&(n / 2 + 1)));
+    assert_eq!(mapped.get(n / 2), Some(&(n / 2 + 1)));
+    assert_eq!(mapped.front(), Some(&1));
+    assert!(count.load(Ordering::SeqCst) < 100);
+}
+
+#[test]
+fn test_stats_empty() {
+    let stats = Seq::<u32>::empty().stats();
+    assert_eq!(stats, SeqStats { evaluated: 1, .. SeqStats::default() });
+}
+
+#[test]
+fn test_stats() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    assert_eq!(seq.iter().count(), n);
+    let stats = seq.stats();
+    assert_eq!(stats.leaves, seq.slices().count());
+    assert_eq!(stats.unevaluated, 0);
+    assert!(stats.depth > 1);
+    let digits: usize = stats.digits.iter().sum();
+    assert_eq!(digits, 2 * stats.depth);
+    assert_eq!(stats.shared, 0);
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_stats_does_not_force() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let count = Arc::new(AtomicUsize::new(0));
+    let mapped = {
+        let count = count.clone();
+        seq.lazy_map(move |x| {
+            count.fetch_add(1, Ordering::SeqCst);
+            x + 1
+        })
+    };
+    let stats = mapped.stats();
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(stats.unevaluated, 1);
+    assert_eq!(stats.leaves, 0);
+    assert_eq!(mapped.iter().count(), n);
+    let stats = mapped.stats();
+    assert_eq!(stats.unevaluated, 0);
+    assert_eq!(stats.leaves, mapped.slices().count());
+}
+
+#[test]
+fn test_stats_shared_with() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let stats = seq.stats_shared_with(&seq);
+    assert_eq!(stats.shared, stats.evaluated + stats.unevaluated + stats.redirects);
+    let pushed = seq.push_back(n);
+    assert_eq!(pushed.iter().count(), n + 1);
+    let shared = pushed.stats_shared_with(&seq).shared;
+    assert!(shared > 0);
+    assert!(shared < stats.shared);
+    let other: Seq<usize> = (0..n).collect();
+    assert_eq!(other.stats_shared_with(&seq).shared, 0);
+}
+
+#[test]
+fn test_to_dot_empty() {
+    let expected = "digraph seq {\n    node [shape=box];\n    v0 [label=\"0\", shape=plaintext];\n    n0 [label=\"Empty\"];\n    v0 -> n0;\n}\n";
+    assert_eq!(Seq::<u32>::empty().to_dot(), expected);
+}
+
+#[test]
+fn test_to_dot() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    assert_eq!(seq.iter().count(), n);
+    let dot = seq.to_dot();
+    assert!(dot.starts_with("digraph"));
+    assert!(dot.contains("Deep"));
+    assert!(!dot.contains("dashed"));
+    assert!(!dot.contains("filled"));
+    assert_eq!(dot.matches("Leaf").count(), seq.slices().count());
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_to_dot_unevaluated() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let dot = seq.lazy_map(|x| x + 1).to_dot();
+    assert!(dot.contains("unevaluated"));
+    assert!(dot.contains("dashed"));
+    assert!(!dot.contains("Leaf"));
+}
+
+#[test]
+fn test_versions_to_dot() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let (before, after) = seq.split(n / 2);
+    let joined = after.append(&before);
+    for s in &[&seq, &before, &after, &joined] {
+        assert_eq!(s.iter().count(), s.len());
+    }
+    let dot = Seq::versions_to_dot(&[&seq, &before, &after, &joined]);
+    assert!(dot.contains("v3 [label=\"3\", shape=plaintext]"));
+    assert!(dot.contains("filled"));
+    let leaves: usize = [&seq, &before, &after, &joined].iter().map(|s| s.slices().count()).sum();
+    assert!(dot.matches("Leaf").count() < leaves / 2);
+}
+
+#[test]
+fn test_check_invariants() {
+    let n = 1000;
+    let mut seq: Seq<usize> = (0..n).collect();
+    assert_eq!(seq.check_invariants(), Ok(()));
+    let (before, after) = seq.split(n / 3);
+    assert_eq!(after.append(&before).check_invariants(), Ok(()));
+    assert_eq!(seq.lazy_map(|x| x + 1).check_invariants(), Ok(()));
+    assert_eq!(seq.map(|x| x + 1).check_invariants(), Ok(()));
+    for i in 0..n {
+        seq.push_front_mut(i);
+        seq.pop_back_mut();
+    }
+    assert_eq!(seq.check_invariants(), Ok(()));
+}
+
+#[test]
+fn test_slices() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    assert!(seq.slices().all(|s| !s.is_empty()));
+    assert!(seq.slices().count() < n / 10);
+    let result: Vec<usize> = seq.slices().flat_map(|s| s.iter().cloned()).collect();
+    let expected: Vec<usize> = (0..n).collect();
+    assert_eq!(result, expected);
+    let result: Vec<usize> = seq.slices().rev().flat_map(|s| s.iter().rev().cloned()).collect();
+    let expected: Vec<usize> = (0..n).rev().collect();
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_push_back_diverging() {
+    let a: Seq<usize> = (0..10).collect();
+    let b = a.push_back(10);
+    let c = a.push_back(20);
+    let d = a.pop_back().push_back(30);
+    assert_eq!(a, (0..10).collect::<Seq<_>>());
+    assert_eq!(b, (0..11).collect::<Seq<_>>());
+    assert_eq!(c, (0..10).chain(Some(20)).collect::<Seq<_>>());
+    assert_eq!(d, (0..9).chain(Some(30)).collect::<Seq<_>>());
+}
+
+#[test]
+fn test_push_front_diverging() {
+    let a: Seq<usize> = (1..10).rev().fold(Seq::empty(), |seq, x| seq.push_front(x));
+    let b = a.push_front(0);
+    let c = a.push_front(20);
+    let d = a.pop_front().push_front(30);
+    assert_eq!(a, (1..10).collect::<Seq<_>>());
+    assert_eq!(b, (0..10).collect::<Seq<_>>());
+    assert_eq!(c, Some(20).into_iter().chain(1..10).collect::<Seq<_>>());
+    assert_eq!(d, Some(30).into_iter().chain(2..10).collect::<Seq<_>>());
+}
+
+#[test]
+fn test_mut_after_persistent_pop() {
+    let a: Seq<usize> = (0..10).collect();
+    let mut b = a.pop_back();
+    b.push_back_mut(30);
+    let mut c = a.pop_front();
+    c.push_front_mut(40);
+    assert_eq!(a, (0..10).collect::<Seq<_>>());
+    assert_eq!(b, (0..9).chain(Some(30)).collect::<Seq<_>>());
+    assert_eq!(c, Some(40).into_iter().chain(1..10).collect::<Seq<_>>());
+}
+
+#[test]
+fn test_update_every_index() {
+    let n = 200;
+    let a: Seq<usize> = (0..n).collect();
+    let mut b = a.clone();
+    for i in 0..n {
+        b = b.update(i, 2 * i);
+    }
+    for i in 0..n {
+        b = b.adjust(i, |x| x + 1);
+    }
+    assert_eq!(a, (0..n).collect::<Seq<_>>());
+    assert_eq!(b, (0..n).map(|x| 2 * x + 1).collect::<Seq<_>>());
+}
+
+#[test]
+fn test_drop_elements() {
+    let x = Arc::new(());
+    {
+        let mut a: Seq<Arc<()>> = (0..100).map(|_| x.clone()).collect();
+        let b = a.push_front(x.clone()).pop_back().insert(50, x.clone()).remove(20);
+        let (c, d) = b.split(33);
+        a.pop_back_mut();
+        a.pop_front_mut();
+        a.get_mut(10);
+        let e = c.append(&d).map(|y| y.clone());
+        assert_eq!(e.len(), 100);
+        a.push_back_mut(x.clone());
+    }
+    assert_eq!(Arc::strong_count(&x), 1);
+}
+
+/// A value whose `clone` panics if it is armed.
+#[derive(Debug, PartialEq)]
+struct Bomb {
+    value: usize,
+    armed: bool,
+}
+
+impl Clone for Bomb {
+    fn clone(&self) -> Bomb {
+        if self.armed {
+            panic!("boom");
+        }
+        Bomb {
+            value: self.value,
+            armed: false,
+        }
+    }
+}
+
+fn bombs(n: usize, armed: usize) -> Seq<Bomb> {
+    (0..n).map(|i| Bomb { value: i, armed: i == armed }).collect()
+}
+
+fn values(seq: &Seq<Bomb>) -> Vec<usize> {
+    seq.iter().map(|b| b.value).collect()
+}
+
+#[cfg(not(feature = "strict"))]
+fn panic_message(err: Box<dyn std::any::Any + Send>) -> String {
+    match err.downcast::<String>() {
+        Ok(s) => *s,
+        Err(err) => err.downcast::<&str>().map(|s| s.to_string()).unwrap_or_default(),
+    }
+}
+
+#[test]
+fn test_adjust_panic_leaves_seq_unchanged() {
+    let seq: Seq<usize> = (0..100).collect();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        seq.adjust(50, |_| panic!("boom")).iter().count()
+    }));
+    assert!(result.is_err());
+    assert_eq!(seq, (0..100).collect::<Seq<_>>());
+    assert_eq!(seq.adjust(50, |x| x + 1).get(50), Some(&51));
+}
+
+#[test]
+fn test_map_panic_leaves_seq_unchanged() {
+    let seq: Seq<usize> = (0..100).collect();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        seq.map(|&x| if x == 70 { panic!("boom") } else { x })
+    }));
+    assert!(result.is_err());
+    assert_eq!(seq, (0..100).collect::<Seq<_>>());
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_lazy_map_panic_poisons() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let mapped = seq.lazy_map(move |&x| if x == n / 2 { panic!("boom") } else { x });
+    assert_eq!(mapped.get(0), Some(&0));
+    let first = panic::catch_unwind(AssertUnwindSafe(|| mapped.get(n / 2).cloned()));
+    assert_eq!(panic_message(first.unwrap_err()), "boom");
+    let second = panic::catch_unwind(AssertUnwindSafe(|| mapped.get(n / 2).cloned()));
+    assert!(panic_message(second.unwrap_err()).contains("poisoned"));
+    assert_eq!(mapped.get(0), Some(&0));
+    assert_eq!(mapped.get(n - 1), Some(&(n - 1)));
+    assert_eq!(seq, (0..n).collect::<Seq<_>>());
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_mut_after_lazy_map_panic() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let mut mapped = seq.lazy_map(move |&x| if x == n - 1 { panic!("boom") } else { x });
+    let result = panic::catch_unwind(AssertUnwindSafe(|| mapped.push_back_mut(n)));
+    assert!(result.is_err());
+    let len = mapped.len();
+    assert!(len < n);
+    assert_eq!(mapped, (0..len).collect::<Seq<_>>());
+    mapped.push_back_mut(n);
+    assert_eq!(mapped.back(), Some(&n));
+    assert_eq!(mapped.len(), len + 1);
+}
+
+#[test]
+fn test_from_iter_panic_drops_elements() {
+    let x = Arc::new(());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        (0..100).map(|i| if i == 70 { panic!("boom") } else { x.clone() }).collect::<Seq<Arc<()>>>()
+    }));
+    assert!(result.is_err());
+    assert_eq!(Arc::strong_count(&x), 1);
+}
+
+#[test]
+fn test_clone_panic_in_mut_leaves_seq_unchanged() {
+    let n = 100;
+    let mut a = bombs(n, n - 1);
+    let b = a.clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| a.pop_back_mut()));
+    assert!(result.is_err());
+    assert_eq!(values(&a), (0..n).collect::<Vec<_>>());
+    assert_eq!(values(&b), (0..n).collect::<Vec<_>>());
+
+    let mut c = bombs(n, n / 2);
+    let d = c.clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| c.get_mut(n / 2 + 1).map(|x| x.value = 0)));
+    assert!(result.is_err());
+    assert_eq!(values(&c), (0..n).collect::<Vec<_>>());
+    assert_eq!(values(&d), (0..n).collect::<Vec<_>>());
+    c.pop_front_mut();
+    assert_eq!(values(&c), (1..n).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_borrowed_elements() {
+    let words: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+    let mut seq: Seq<&str> = Seq::empty();
+    for word in &words {
+        seq = seq.push_back(word.as_str());
+    }
+    let (left, right) = seq.split(500);
+    let seq = right.append(&left).pop_front().push_front("first");
+    assert_eq!(seq.len(), 1000);
+    assert_eq!(seq.front(), Some(&"first"));
+    assert_eq!(seq.get(1), Some(&"501"));
+    assert_eq!(seq.back(), Some(&"499"));
+    let lengths = seq.map(|word| word.len());
+    assert_eq!(lengths.iter().sum::<usize>(), seq.iter().map(|word| word.len()).sum::<usize>());
+}
+
+/// A node of a syntax tree, which refers to its children.
+#[derive(Debug, PartialEq)]
+struct Expr<'a> {
+    name: &'a str,
+    children: Seq<&'a Expr<'a>>,
+}
+
+#[test]
+fn test_borrowed_nodes() {
+    let x = Expr { name: "x", children: Seq::empty() };
+    let y = Expr { name: "y", children: Seq::empty() };
+    let sum = Expr { name: "+", children: seq![&x, &y] };
+    let product = Expr { name: "*", children: seq![&sum, &x] };
+    let names: Vec<&str> = product.children.iter()
+        .flat_map(|child| Some(child.name).into_iter().chain(child.children.iter().map(|c| c.name)))
+        .collect();
+    assert_eq!(names, vec!["+", "x", "y", "x"]);
+    assert_eq!(product.children.pop_back().get(0), Some(&&sum));
+}
+
+#[test]
+fn test_borrowed_stack_data() {
+    let outer: Vec<usize> = (0..100).collect();
+    let base: Seq<&usize> = outer.iter().collect();
+    {
+        let inner = [1000, 1001];
+        // `copied` would keep the lifetime of `outer`, which `inner` does not live for; the closure shortens it.
+        #[allow(clippy::map_clone)]
+        let mut seq: Seq<&usize> = base.iter().map(|x| *x).chain(inner.iter()).collect();
+        seq.push_front_mut(&inner[1]);
+        assert_eq!(seq.len(), 103);
+        assert_eq!(seq.front(), Some(&&1001));
+        assert_eq!(seq.get(100), Some(&&99));
+        assert_eq!(seq.back(), Some(&&1001));
+        let (left, right) = seq.split(50);
+        assert_eq!(right.append(&left).get(53), Some(&&1001));
+    }
+    assert_eq!(base.iter().map(|&&x| x).sum::<usize>(), 4950);
+}
+
+#[test]
+fn test_default_is_empty() {
+    let seq: Seq<u32> = Default::default();
+    assert!(seq.is_empty());
+}
+
+#[test]
+fn test_eq_other_collections() {
+    let seq: Seq<u32> = (0..100).collect();
+    let v: Vec<u32> = (0..100).collect();
+    let d: std::collections::VecDeque<u32> = (0..100).collect();
+    assert_eq!(seq, v);
+    assert_eq!(v, seq);
+    assert_eq!(seq, v[..]);
+    assert_eq!(&v[..], seq);
+    assert_eq!(seq, d);
+    assert_eq!(d, seq);
+    assert_eq!(Seq::from([1, 2, 3]), [1, 2, 3]);
+    assert!(seq != v[..99]);
+    assert!(seq.push_back(0) != v);
+}
+
+#[test]
+fn test_hash_ignores_shape() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    fn hash(seq: &Seq<u32>) -> u64 {
+        let mut h = DefaultHasher::new();
+        seq.hash(&mut h);
+        h.finish()
+    }
+    let collected: Seq<u32> = (0..1000).collect();
+    let pushed = (0..1000).rev().fold(Seq::empty(), |seq, x| seq.push_front(x));
+    let (left, right) = collected.split(317);
+    assert_eq!(hash(&collected), hash(&pushed));
+    assert_eq!(hash(&collected), hash(&left.append(&right)));
+}
+
+#[test]
+fn test_extend_and_conversions() {
+    let mut seq: Seq<u32> = Seq::from(&[0, 1, 2][..]);
+    seq.extend(3..50);
+    seq.extend(&[50, 51]);
+    let expected: Vec<u32> = (0..52).collect();
+    assert_eq!(seq, expected);
+    let from_deque = Seq::from(expected.iter().cloned().collect::<std::collections::VecDeque<u32>>());
+    assert_eq!(from_deque, seq);
+    let refs: Seq<u32> = expected.iter().collect();
+    assert_eq!(refs, seq);
+    assert_eq!(Vec::from(seq.clone()), expected);
+    assert_eq!(std::collections::VecDeque::from(seq), expected);
+}
+
+#[test]
+fn test_into_iter_owned() {
+    let seq: Seq<String> = (0..200).map(|i| i.to_string()).collect();
+    let shared = seq.truncate(100);
+    let forwards: Vec<String> = seq.clone().into_iter().collect();
+    let backwards: Vec<String> = seq.into_iter().rev().collect();
+    let expected: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+    assert_eq!(forwards, expected);
+    assert_eq!(backwards, expected.iter().rev().cloned().collect::<Vec<_>>());
+    assert_eq!(shared.into_iter().len(), 100);
+}
+
+#[test]
+fn test_sum_concatenates() {
+    let parts: Vec<Seq<u32>> = (0..10).map(|i| (i * 10..i * 10 + 10).collect()).collect();
+    let expected: Vec<u32> = (0..100).collect();
+    assert_eq!(parts.iter().sum::<Seq<u32>>(), expected);
+    assert_eq!(parts.into_iter().sum::<Seq<u32>>(), expected);
+}
+
+#[test]
+fn test_index_mut() {
+    let seq: Seq<u32> = (0..100).collect();
+    let mut copy = seq.clone();
+    copy[42] = 1000;
+    assert_eq!(copy[42], 1000);
+    assert_eq!(seq[42], 42);
+}
+
+#[test]
+fn test_subseq_and_range() {
+    let seq: Seq<u32> = (0..1000).collect();
+    for &(start, end) in &[(0usize, 0), (0, 1000), (10, 20), (0, 317), (500, 1000), (999, 1000), (1000, 1000)] {
+        let expected: Vec<u32> = (start as u32..end as u32).collect();
+        assert_eq!(seq.subseq(start..end), expected);
+        assert_eq!(seq.range(start..end).cloned().collect::<Vec<_>>(), expected);
+        assert_eq!(seq.range(start..end).rev().cloned().collect::<Vec<_>>(), expected.iter().rev().cloned().collect::<Vec<_>>());
+    }
+    assert_eq!(seq.subseq(..), seq);
+    assert_eq!(seq.subseq(..=9), (0..10).collect::<Vec<_>>());
+    assert_eq!(seq.range(990..).len(), 10);
+}
+
+#[test]
+#[should_panic(expected = "range end index 11 out of range for sequence of length 10")]
+fn test_subseq_past_end_should_panic() {
+    let seq: Seq<u32> = (0..10).collect();
+    seq.subseq(5..11);
+}
+
+#[test]
+#[should_panic(expected = "sequence index starts at 6 but ends at 5")]
+fn test_range_backwards_should_panic() {
+    let seq: Seq<u32> = (0..10).collect();
+    let (start, end) = (6, 5);
+    seq.range(start..end);
+}
+
+#[test]
+fn test_reverse() {
+    for &n in &[0, 1, 2, 31, 32, 33, 100, 1000, 10000] {
+        let seq: Seq<usize> = (0..n).collect();
+        let pushed = (0..n).rev().fold(Seq::empty(), |seq, x| seq.push_front(x));
+        let expected: Vec<usize> = (0..n).rev().collect();
+        for seq in &[seq, pushed] {
+            let reversed = seq.reverse();
+            assert_eq!(reversed.len(), n);
+            assert_eq!(reversed, expected);
+            assert_eq!(reversed.check_invariants(), Ok(()));
+            assert_eq!(reversed.reverse(), *seq);
+            assert_eq!(reversed.front(), seq.back());
+            assert_eq!(reversed.back(), seq.front());
+        }
+    }
+}
+
+#[test]
+fn test_reverse_then_modify() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let reversed = seq.reverse();
+    let (left, right) = reversed.split(300);
+    assert_eq!(left, (700..n).rev().collect::<Vec<_>>());
+    assert_eq!(right, (0..700).rev().collect::<Vec<_>>());
+    let mixed = seq.append(&reversed).append(&seq.reverse().reverse());
+    let expected: Vec<usize> = (0..n).chain((0..n).rev()).chain(0..n).collect();
+    assert_eq!(mixed, expected);
+    let mut pushed = reversed.push_front(n).push_back(n + 1);
+    pushed.push_back_mut(n + 2);
+    assert_eq!(pushed.pop_front_mut(), Some(n));
+    assert_eq!(pushed.get(0), Some(&(n - 1)));
+    assert_eq!(pushed.get(n), Some(&(n + 1)));
+    assert_eq!(pushed.len(), n + 2);
+    assert_eq!(reversed.update(0, 0).get(0), Some(&0));
+    assert_eq!(reversed.get(0), Some(&(n - 1)));
+}
+
+#[derive(Debug)]
+#[cfg(not(feature = "strict"))]
+struct Counted(usize, Arc<AtomicUsize>);
+
+#[cfg(not(feature = "strict"))]
+impl Clone for Counted {
+    fn clone(&self) -> Counted {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        Counted(self.0, self.1.clone())
+    }
+}
+
+#[test]
+#[cfg(not(feature = "strict"))]
+fn test_reverse_is_lazy() {
+    let n = 100000;
+    let count = Arc::new(AtomicUsize::new(0));
+    let seq: Seq<Counted> = (0..n).map(|i| Counted(i, count.clone())).collect();
+    let reversed = seq.reverse();
+    assert_eq!(count.load(Ordering::SeqCst), 0);
+    assert_eq!(reversed.front().map(|x| x.0), Some(n - 1));
+    assert_eq!(reversed.back().map(|x| x.0), Some(0));
+    assert_eq!(reversed.get(n / 3).map(|x| x.0), Some(n - 1 - n / 3));
+    assert!(count.load(Ordering::SeqCst) < 1000);
+}
+
+#[test]
+fn test_insert_seq() {
+    let seq: Seq<usize> = (0..1000).collect();
+    let other: Seq<usize> = (5000..5100).collect();
+    for &i in &[0, 1, 317, 999, 1000, 2000] {
+        let mut expected: Vec<usize> = (0..1000).collect();
+        let at = i.min(1000);
+        expected.splice(at..at, 5000..5100);
+        let inserted = seq.insert_seq(i, &other);
+        assert_eq!(inserted, expected);
+        assert_eq!(inserted.check_invariants(), Ok(()));
+    }
+}
+
+#[test]
+fn test_replace_and_remove_range() {
+    let seq: Seq<usize> = (0..1000).collect();
+    let other: Seq<usize> = (5000..5010).collect();
+    for &(start, end) in &[(0, 0), (0, 1000), (10, 20), (0, 317), (500, 1000), (999, 1000), (1000, 1000)] {
+        let mut expected: Vec<usize> = (0..1000).collect();
+        expected.splice(start..end, 5000..5010);
+        let replaced = seq.replace_range(start..end, &other);
+        assert_eq!(replaced, expected);
+        assert_eq!(replaced.check_invariants(), Ok(()));
+        let mut expected: Vec<usize> = (0..1000).collect();
+        let removed: Vec<usize> = expected.drain(start..end).collect();
+        let (rest, out) = seq.remove_range(start..end);
+        assert_eq!(rest, expected);
+        assert_eq!(out, removed);
+    }
+    assert_eq!(seq.remove_range(..).0.len(), 0);
+    assert_eq!(seq.replace_range(..=499, &Seq::empty()), (500..1000).collect::<Vec<_>>());
+}
+
+#[test]
+#[should_panic(expected = "range end index 11 out of range for sequence of length 10")]
+fn test_replace_range_past_end_should_panic() {
+    let seq: Seq<u32> = (0..10).collect();
+    seq.replace_range(5..11, &Seq::empty());
+}
+
+#[test]
+fn test_rotate() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    for &k in &[0, 1, 31, 500, 999, 1000] {
+        let mut expected: Vec<usize> = (0..n).collect();
+        expected.rotate_left(k);
+        assert_eq!(seq.rotate_left(k), expected);
+        let mut expected: Vec<usize> = (0..n).collect();
+        expected.rotate_right(k);
+        assert_eq!(seq.rotate_right(k), expected);
+        assert_eq!(seq.rotate_left(k).rotate_right(k), seq);
+    }
+}
+
+#[test]
+#[should_panic(expected = "rotate by 11 is out of range for sequence of length 10")]
+fn test_rotate_past_end_should_panic() {
+    let seq: Seq<u32> = (0..10).collect();
+    seq.rotate_right(11);
+}
+
+#[test]
+fn test_get_many() {
+    let n = 10000;
+    let seq: Seq<usize> = (0..n).collect();
+    let pushed = (0..n).rev().fold(Seq::empty(), |seq, x| seq.push_front(x));
+    let indices = [0, 1, 31, 32, 33, 500, 500, 4999, 9998, 9999, 10000, 20000];
+    let unsorted = [9999, 3, 20000, 0, 500, 3, 7777];
+    for seq in &[seq, pushed] {
+        for indices in &[&indices[..], &unsorted[..], &[]] {
+            let expected: Vec<Option<&usize>> = indices.iter().map(|&i| seq.get(i)).collect();
+            assert_eq!(seq.get_many(indices), expected);
+        }
+        let all: Vec<usize> = (0..n).collect();
+        let found: Vec<usize> = seq.get_many(&all).into_iter().map(|x| *x.unwrap()).collect();
+        assert_eq!(found, all);
+    }
+    assert_eq!(Seq::<u32>::empty().get_many(&[0, 1]), vec![None, None]);
+}
+
+#[test]
+fn test_update_and_adjust_many() {
+    let n = 10000;
+    let seq: Seq<usize> = (0..n).collect();
+    let updates = vec![(9999, 1), (0, 2), (500, 3), (20000, 4), (31, 5), (500, 6), (32, 7)];
+    let mut expected: Vec<usize> = (0..n).collect();
+    for &(i, x) in &updates {
+        if i < n {
+            expected[i] = x;
+        }
+    }
+    let updated = seq.update_many(updates.iter().cloned());
+    assert_eq!(updated, expected);
+    assert_eq!(updated.check_invariants(), Ok(()));
+    assert_eq!(seq, (0..n).collect::<Vec<_>>());
+
+    let indices = vec![7, 7, 7, 100, 9999, 12345, 3];
+    let mut expected: Vec<usize> = (0..n).collect();
+    for &i in &indices {
+        if i < n {
+            expected[i] *= 2;
+        }
+    }
+    let adjusted = seq.adjust_many(indices, |x| x * 2);
+    assert_eq!(adjusted, expected);
+    assert_eq!(adjusted.check_invariants(), Ok(()));
+    let everything = seq.adjust_many(0..n, |x| x + 1);
+    assert_eq!(everything, (1..n + 1).collect::<Vec<_>>());
+    assert_eq!(seq.update_many(None), seq);
+}
+
+#[test]
+fn test_split_at_many() {
+    let n = 1000;
+    let seq: Seq<usize> = (0..n).collect();
+    let all: Vec<usize> = (0..n).collect();
+    for indices in &[vec![], vec![0], vec![1000], vec![0, 0, 5], vec![10, 20, 317, 999], vec![500, 2000, 3000]] {
+        let pieces = seq.split_at_many(indices);
+        assert_eq!(pieces.len(), indices.len() + 1);
+        let mut start = 0;
+        for (piece, &end) in pieces.iter().zip(indices.iter().chain(Some(&n))) {
+            let end = end.min(n);
+            assert_eq!(*piece, all[start..end]);
+            assert_eq!(piece.check_invariants(), Ok(()));
+            start = end;
+        }
+        assert_eq!(pieces.iter().sum::<Seq<usize>>(), seq);
+    }
+}
+
+#[test]
+#[should_panic(expected = "split indices must be sorted")]
+fn test_split_at_many_unsorted_should_panic() {
+    let seq: Seq<u32> = (0..10).collect();
+    seq.split_at_many(&[5, 3]);
+}
+
+#[test]
+fn test_partition_into() {
+    for &n in &[0, 1, 7, 100, 1001] {
+        let seq: Seq<usize> = (0..n).collect();
+        for &k in &[1, 2, 3, 8, 200] {
+            let parts = seq.partition_into(k);
+            assert_eq!(parts.len(), k);
+            let lens: Vec<usize> = parts.iter().map(|p| p.len()).collect();
+            assert!(lens.windows(2).all(|w| w[0] >= w[1] && w[0] - w[1] <= 1), "{:?}", lens);
+            assert_eq!(parts.iter().sum::<Seq<usize>>(), seq);
+        }
+    }
+}
+
+#[test]
+fn test_chunks() {
+    let all: Vec<usize> = (0..1000).collect();
+    let seq: Seq<usize> = all.iter().cloned().collect();
+    for &size in &[1, 3, 32, 100, 999, 1000, 5000] {
+        let chunks: Vec<Seq<usize>> = seq.chunks(size).collect();
+        let expected: Vec<&[usize]> = all.chunks(size).collect();
+        assert_eq!(seq.chunks(size).len(), expected.len());
+        assert_eq!(chunks.len(), expected.len());
+        for (chunk, slice) in chunks.iter().zip(&expected) {
+            assert_eq!(chunk, slice);
+        }
+        let backwards: Vec<Seq<usize>> = seq.chunks(size).rev().collect();
+        for (chunk, slice) in backwards.iter().zip(expected.iter().rev()) {
+            assert_eq!(chunk, slice);
+        }
+    }
+    assert_eq!(Seq::<u32>::empty().chunks(3).count(), 0);
+}
+
+#[test]
+fn test_windows() {
+    let all: Vec<usize> = (0..100).collect();
+    let seq: Seq<usize> = all.iter().cloned().collect();
+    for &size in &[1, 2, 33, 100, 101] {
+        let windows: Vec<Seq<usize>> = seq.windows(size).collect();
+        let expected: Vec<&[usize]> = all.windows(size).collect();
+        assert_eq!(seq.windows(size).len(), expected.len());
+        assert_eq!(windows.len(), expected.len());
+        for (window, slice) in windows.iter().zip(&expected) {
+            assert_eq!(window, slice);
+        }
+        let backwards: Vec<Seq<usize>> = seq.windows(size).rev().collect();
+        for (window, slice) in backwards.iter().zip(expected.iter().rev()) {
+            assert_eq!(window, slice);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "chunk size must be non-zero")]
+fn test_chunks_zero_should_panic() {
+    Seq::<u32>::empty().chunks(0);
+}
+
+fn shapes(n: usize) -> Vec<Seq<usize>> {
+    let collected: Seq<usize> = (0..n).collect();
+    let pushed = (0..n).rev().fold(Seq::empty(), |seq, x| seq.push_front(x));
+    let (left, right) = collected.split(n / 3);
+    vec![collected.clone(), pushed, left.append(&right), collected.reverse().reverse()]
+}
+
+#[test]
+fn test_filter() {
+    for &n in &[0, 1, 31, 1000, 20000] {
+        for seq in shapes(n) {
+            type Pred = Box<dyn Fn(&usize) -> bool>;
+            let preds: Vec<Pred> = vec![
+                Box::new(|_| true),
+                Box::new(|_| false),
+                Box::new(|x| x % 3 == 0),
+                Box::new(move |&x| x != n / 2),
+                Box::new(move |&x| x < n / 4 || x > n / 2),
+            ];
+            for pred in &preds {
+                let expected: Vec<usize> = (0..n).filter(|x| pred(x)).collect();
+                let filtered = seq.filter(|x| pred(x));
+                assert_eq!(filtered, expected);
+                assert_eq!(filtered.check_invariants(), Ok(()));
+                let mut retained = seq.clone();
+                retained.retain(|x| pred(x));
+                assert_eq!(retained, expected);
+                let (matching, rest) = seq.partition(|x| pred(x));
+                assert_eq!(matching, expected);
+                assert_eq!(rest, (0..n).filter(|x| !pred(x)).collect::<Vec<_>>());
+                assert_eq!(rest.check_invariants(), Ok(()));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_filter_calls_pred_in_order() {
+    let seq = shapes(5000).pop().unwrap();
+    let mut seen = Vec::new();
+    seq.filter(|&x| {
+        seen.push(x);
+        x % 2 == 0
+    });
+    assert_eq!(seen, (0..5000).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_filter_shares_unchanged_nodes() {
+    let n = 100000;
+    let seq: Seq<usize> = (0..n).collect();
+    let filtered = seq.filter(|&x| x != n / 2);
+    assert_eq!(filtered.iter().count(), n - 1);
+    let stats = filtered.stats_shared_with(&seq);
+    let total = stats.evaluated + stats.unevaluated;
+    assert!(stats.shared * 10 > total * 9, "{:?}", stats);
+    let everything = seq.filter(|_| true);
+    assert_eq!(everything.stats_shared_with(&seq).shared, seq.stats().evaluated + seq.stats().unevaluated);
+}
+
+#[test]
+fn test_dedup() {
+    let values = vec![1, 1, 2, 3, 3, 3, 1, 4, 4, 5, 5, 5, 5, 1];
+    let mut long: Vec<usize> = Vec::new();
+    for i in 0..3000 {
+        long.extend(values.iter().map(|x| x + i / 7));
+    }
+    let seq: Seq<usize> = long.iter().cloned().collect();
+    let mut expected = long.clone();
+    expected.dedup();
+    assert_eq!(seq.dedup(), expected);
+    let mut expected = long.clone();
+    expected.dedup_by_key(|x| *x / 2);
+    assert_eq!(seq.dedup_by_key(|x| *x / 2), expected);
+    let distinct: Seq<usize> = (0..1000).collect();
+    assert_eq!(distinct.dedup(), distinct);
+}
+
+#[test]
+fn test_flat_map() {
+    for seq in shapes(1000) {
+        let expected: Vec<usize> = (0..1000).flat_map(|x| 0..x % 4).collect();
+        let result = seq.flat_map(|&x| 0..x % 4);
+        assert_eq!(result, expected);
+        assert_eq!(result.check_invariants(), Ok(()));
+    }
+    assert!(Seq::<u32>::empty().flat_map(|&x| Some(x)).is_empty());
+}
+
+#[test]
+fn test_repeat() {
+    for &n in &[0, 1, 31, 32, 33, 64, 1000, 12345] {
+        let seq = Seq::repeat(n, n);
+        assert_eq!(seq, vec![n; n]);
+        assert_eq!(seq.check_invariants(), Ok(()));
+        assert_eq!(seq![n; n], seq);
+    }
+}
+
+#[test]
+fn test_cycle() {
+    let seq: Seq<usize> = (0..100).collect();
+    for &k in &[0, 1, 2, 3, 7, 8, 100] {
+        let cycled = seq.cycle(k);
+        let expected: Vec<usize> = (0..k).flat_map(|_| 0..100).collect();
+        assert_eq!(cycled, expected);
+        assert_eq!(cycled.check_invariants(), Ok(()));
+    }
+    assert!(Seq::<u32>::empty().cycle(1000).is_empty());
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn test_repeat_shares_nodes() {
+    let n = 1 << 40;
+    let seq = Seq::repeat(String::from("x"), n + 5);
+    assert_eq!(seq.len(), n + 5);
+    assert_eq!(seq.get(n / 3).map(|s| &s[..]), Some("x"));
+    assert_eq!(seq.get(n + 4).map(|s| &s[..]), Some("x"));
+    let updated = seq.update(n / 2, String::from("y"));
+    assert_eq!(updated.get(n / 2).map(|s| &s[..]), Some("y"));
+    assert_eq!(updated.get(n / 2 + 1).map(|s| &s[..]), Some("x"));
+    let stats = seq.stats();
+    assert!(stats.evaluated + stats.unevaluated < 10000, "{:?}", stats);
+}
+
+#[test]
+fn test_zip() {
+    for a in shapes(1000) {
+        for &m in &[0, 1, 500, 1000, 2000] {
+            let b: Seq<String> = (0..m).map(|i| i.to_string()).collect();
+            let zipped = a.zip(&b);
+            let expected: Vec<(usize, String)> = (0..1000).zip((0..m).map(|i| i.to_string())).collect();
+            assert_eq!(zipped, expected);
+            assert_eq!(zipped.check_invariants(), Ok(()));
+            let sums = a.zip_with(&b, |x, y| x + y.len());
+            assert_eq!(sums, expected.iter().map(|&(x, ref y)| x + y.len()).collect::<Vec<_>>());
+            let (left, right) = zipped.unzip();
+            assert_eq!(left, expected.iter().map(|p| p.0).collect::<Vec<_>>());
+            assert_eq!(right, expected.iter().map(|p| p.1.clone()).collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn test_scan() {
+    for seq in shapes(1000) {
+        let totals = seq.scan(10, |total, x| total + x);
+        let expected: Vec<usize> = (0..1000).scan(10, |total, x| {
+            *total += x;
+            Some(*total)
+        }).collect();
+        assert_eq!(totals, expected);
+        assert_eq!(totals.check_invariants(), Ok(()));
+    }
+    assert!(Seq::<u32>::empty().scan(0, |s, x| s + x).is_empty());
+}
@@ -0,0 +1,59 @@
+#![cfg(feature = "proptest")]
+
+extern crate proptest;
+extern crate immutable_seq;
+
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+
+use immutable_seq::{Seq, strategy};
+
+proptest! {
+    #[test]
+    fn prop_seq_invariants(seq in strategy::seq(any::<u32>(), 0..300)) {
+        prop_assert_eq!(seq.check_invariants(), Ok(()));
+        prop_assert!(seq.len() < 300);
+    }
+
+    #[test]
+    fn prop_split_append(seq in any::<Seq<u32>>(), i in 0..200usize) {
+        let (left, right) = seq.split(i);
+        prop_assert_eq!(left.len(), std::cmp::min(i, seq.len()));
+        prop_assert_eq!(left.append(&right), seq);
+    }
+
+    #[test]
+    fn prop_matches_vec(seq in strategy::seq(any::<u32>(), 0..300)) {
+        let vec: Vec<u32> = seq.iter().cloned().collect();
+        let collected: Seq<u32> = vec.iter().cloned().collect();
+        prop_assert_eq!(collected, seq);
+    }
+}
+
+#[test]
+fn test_strategy_shapes() {
+    let mut runner = proptest::test_runner::TestRunner::deterministic();
+    let strategy = strategy::seq(any::<u32>(), 100..200);
+    let mut reshaped = 0;
+    for _ in 0..100 {
+        let seq = strategy.new_tree(&mut runner).unwrap().current();
+        let collected: Seq<u32> = seq.iter().cloned().collect();
+        if collected.stats() != seq.stats() {
+            reshaped += 1;
+        }
+    }
+    assert!(reshaped > 50);
+}
+
+#[test]
+fn test_strategy_shrinks_to_shorter() {
+    let mut runner = proptest::test_runner::TestRunner::deterministic();
+    let result = runner.run(&strategy::seq(any::<u32>(), 0..300), |seq| {
+        prop_assert!(seq.len() < 10);
+        Ok(())
+    });
+    match result {
+        Err(proptest::test_runner::TestError::Fail(_, seq)) => assert_eq!(seq.len(), 10),
+        other => panic!("expected a failure, got {:?}", other),
+    }
+}
@@ -0,0 +1,131 @@
+//! Operations on very long sequences, run on threads with small stacks, to check that nothing recurses in proportion to the length of a sequence.
+
+extern crate immutable_seq;
+
+use std::thread;
+
+use immutable_seq::Seq;
+
+/// Run `f` on a new thread with a stack of `size` bytes.
+fn with_stack<F>(size: usize, f: F)
+    where F: FnOnce() + Send + 'static
+{
+    thread::Builder::new()
+        .stack_size(size)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+const SMALL_STACK: usize = 256 * 1024;
+
+const TINY_STACK: usize = 64 * 1024;
+
+/// The length of the sequences, shorter under Miri, which is too slow for the full length.
+const LONG: usize = if cfg!(miri) { 2000 } else { 2_000_000 };
+
+#[test]
+#[cfg(feature = "std")]
+fn test_drop_long_push_back() {
+    with_stack(SMALL_STACK, || {
+        let mut seq = Seq::empty();
+        for i in 0..LONG {
+            seq = seq.push_back(i);
+        }
+        assert_eq!(seq.len(), LONG);
+        drop(seq);
+    });
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_drop_long_push_front() {
+    with_stack(SMALL_STACK, || {
+        let mut seq = Seq::empty();
+        for i in 0..LONG {
+            seq = seq.push_front(i);
+        }
+        assert_eq!(seq.len(), LONG);
+        drop(seq);
+    });
+}
+
+#[test]
+fn test_drop_long_collect() {
+    with_stack(SMALL_STACK, || {
+        let seq: Seq<usize> = (0..LONG).collect();
+        assert_eq!(seq.len(), LONG);
+        drop(seq);
+    });
+}
+
+/// A tree whose nodes hold their children in a `Seq`.
+#[cfg(feature = "std")]
+struct Rose(Seq<Rose>);
+
+#[test]
+#[cfg(feature = "std")]
+fn test_drop_nested() {
+    with_stack(SMALL_STACK, || {
+        let mut rose = Rose(Seq::empty());
+        for _ in 0..LONG / 10 {
+            rose = Rose(Seq::singleton(rose));
+        }
+        assert_eq!(rose.0.len(), 1);
+        drop(rose);
+    });
+}
+
+#[test]
+fn test_force_long_push_back() {
+    with_stack(TINY_STACK, || {
+        let mut seq = Seq::empty();
+        for i in 0..LONG {
+            seq = seq.push_back(i);
+        }
+        assert_eq!(seq.get(LONG / 2), Some(&(LONG / 2)));
+        assert_eq!(seq.front(), Some(&0));
+        assert_eq!(seq.pop_front().front(), Some(&1));
+        assert_eq!(seq.iter().sum::<usize>(), LONG * (LONG - 1) / 2);
+    });
+}
+
+#[test]
+fn test_force_long_push_front() {
+    with_stack(TINY_STACK, || {
+        let mut seq = Seq::empty();
+        for i in 0..LONG {
+            seq = seq.push_front(i);
+        }
+        assert_eq!(seq.get(LONG / 2), Some(&(LONG - 1 - LONG / 2)));
+        assert_eq!(seq.back(), Some(&0));
+        assert_eq!(seq.pop_back().back(), Some(&1));
+    });
+}
+
+#[test]
+fn test_force_long_append() {
+    with_stack(TINY_STACK, || {
+        let n = LONG / 10;
+        let mut seq = Seq::empty();
+        for i in 0..n {
+            seq = seq.append(&Seq::singleton(i));
+        }
+        assert_eq!(seq.len(), n);
+        assert_eq!(seq.get(n / 2), Some(&(n / 2)));
+    });
+}
+
+#[test]
+fn test_force_long_lazy_map() {
+    with_stack(TINY_STACK, || {
+        let mut seq = Seq::empty();
+        for i in 0..LONG {
+            seq = seq.push_back(i);
+        }
+        let mapped = seq.lazy_map(|x| x + 1);
+        assert_eq!(mapped.get(LONG / 2), Some(&(LONG / 2 + 1)));
+        assert_eq!(mapped.back(), Some(&LONG));
+    });
+}